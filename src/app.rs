@@ -1,17 +1,55 @@
 // SPDX-License-Identifier: MPL-2.0
 
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
 
 use crate::checker;
-use crate::checker::status::InterfaceStatus;
-use crate::config::Config;
+use crate::checker::history::{Bucket, History, Sample};
+use crate::checker::host_key::HostKey;
+use crate::checker::journal::{self, Journal, JournalEntry, JournalEvent};
+use crate::checker::sla::{SlaStats, SlaWindow};
+use crate::checker::status::{AppError, HttpScheme, InterfaceStatus, OpenWrtConfig, Transport};
+use crate::checker::traffic::{CounterSample, DeviceCounters, Throughput};
+use crate::checker::transport::AnyTransport;
+use crate::checker::usage::{self, UsageStore};
+use crate::config::{Config, RouterProfile};
+use crate::notifications::{self, ChangeKind, RateLimiter};
+use crate::panel::{self, PanelTemplate, Placeholder};
+use crate::secrets;
+use crate::settings::{SettingsErrors, SettingsField, SettingsForm};
 use cosmic::cosmic_config::{self, CosmicConfigEntry};
-use cosmic::iced::{window::Id, Limits, Subscription};
+use cosmic::iced::{window::Id, Length, Limits, Subscription};
 use cosmic::iced_widget::button;
 use cosmic::iced_winit::commands::popup::{destroy_popup, get_popup};
 use cosmic::prelude::*;
 use cosmic::widget;
-use futures_util::SinkExt;
+use futures_util::{SinkExt, StreamExt};
+
+/// Seconds between status polls while no event stream is open.
+const POLL_INTERVAL_SECS: u64 = 60;
+/// Seconds to wait before reopening the event stream after it broke.
+const EVENT_RETRY_SECS: u64 = 30;
+/// Seconds between reads of the device traffic counters while rates are on screen.
+///
+/// Routers reached by spawning `ssh` are otherwise only read every `POLL_INTERVAL_SECS`.
+const TRAFFIC_INTERVAL_SECS: u64 = 10;
+/// Seconds between checks whether a restarted interface is up again.
+const RESTART_POLL_SECS: u64 = 2;
+/// Seconds a restarted interface gets to come back up before the restart counts as failed.
+const RESTART_TIMEOUT_SECS: u64 = 120;
+/// Columns of the traffic graph in the popup.
+const GRAPH_COLUMNS: usize = 40;
+/// Failed refreshes in a row after which a router counts as unreachable rather than stale.
+const FAILURES_UNTIL_UNREACHABLE: u32 = 3;
+/// Seconds between writes of the data usage totals, unless a quota alert fires.
+const USAGE_SAVE_SECS: u64 = 60;
+/// Most recent journal entries listed in the popup.
+const JOURNAL_ROWS: usize = 200;
+/// Height of the journal list in the popup, beyond which it scrolls.
+const JOURNAL_HEIGHT: f32 = 160.0;
 
 /// The application model stores app-specific state used to describe its interface and
 /// drive its logic.
@@ -21,21 +59,223 @@ pub struct AppModel {
     core: cosmic::Core,
     /// The popup id.
     popup: Option<Id>,
+    /// Handle used to persist configuration changes made from the popup.
+    config_handler: Option<cosmic_config::Config>,
     /// Configuration data that persists between application runs.
     config: Config,
 
-    interface_status: Option<InterfaceStatus>,
+    /// Live state of each configured router, by profile name.
+    routers: BTreeMap<String, RouterState>,
+    /// The page currently shown in the popup.
+    page: PopupPage,
+    /// Profile the settings form edits, `None` while adding a new router.
+    editing: Option<String>,
+    /// Unsaved values of the router connection settings form.
+    settings: SettingsForm,
+    /// What is wrong with `settings`, checked as it is edited rather than on every redraw.
+    settings_errors: SettingsErrors,
+    /// Time span the traffic graphs cover.
+    history_window: HistoryWindow,
+    /// Outcome of the last "Test connection" run.
+    connection_test: Option<Result<String, AppError>>,
+    testing_connection: bool,
+    /// Data transferred per interface in the current billing period.
+    usage: UsageStore,
+    /// File `usage` is kept in, `None` when there is no home directory.
+    usage_path: Option<PathBuf>,
+    /// When `usage` was last written to `usage_path`.
+    usage_saved: Option<Instant>,
+    /// Keeps flapping interfaces from flooding the desktop with notifications.
+    notification_limiter: RateLimiter,
+    /// Interface events of all routers, kept across runs.
+    journal: Journal,
+    /// Panel template as typed, saved to the config once it parses.
+    panel_template: String,
+    /// Whether the router passwords were read from the keyring yet.
+    passwords_loaded: bool,
+}
+
+/// What is currently known about one router.
+#[derive(Debug, Default)]
+struct RouterState {
+    /// Last fetched status of each monitored interface the router knows.
+    interface_statuses: BTreeMap<String, InterfaceStatus>,
+    /// When `interface_statuses` was received, to keep the shown uptime running.
+    status_received: Option<Instant>,
+    /// Wall clock time of `status_received`, shown once the statuses go stale.
+    fetched_at: Option<DateTime<Local>>,
+    /// Whether interface events arrive from the router, which makes polling unnecessary.
+    listening: bool,
+    /// Host key offered by the router, waiting for the user to trust it.
+    pending_host_key: Option<HostKey>,
+    /// Set when the router presents a different key than the pinned one.
+    host_key_mismatch: bool,
+    /// Why the last fetch failed, cleared once one succeeds.
+    error: Option<AppError>,
+    /// Fetches that failed since the last successful one.
+    failures: u32,
+    /// Restarts of interfaces by name, kept until the popup is opened again.
+    restarts: BTreeMap<String, Restart>,
+    /// When the traffic counters were last asked for.
+    counters_requested: Option<Instant>,
+    /// Last traffic counters of each device on the router.
+    counters: BTreeMap<String, CounterSample>,
+    /// Transfer rates of each device between its last two counter samples.
+    rates: BTreeMap<String, Throughput>,
+    /// Recent state and traffic of each monitored interface.
+    history: BTreeMap<String, History>,
+    /// Availability of each monitored interface over [`SlaWindow::ALL`], recomputed when
+    /// the status or journal changes and as time passes.
+    sla: BTreeMap<String, [SlaStats; 3]>,
+}
+
+/// Progress of an interface restart the user asked for.
+#[derive(Debug, Clone)]
+enum Restart {
+    /// Waiting for the interface to come back up.
+    Running,
+    /// The interface was up again after this long.
+    Done(Duration),
+    Failed(AppError),
+}
+
+/// State of routers nothing has been received from yet.
+static NO_STATE: RouterState = RouterState {
+    interface_statuses: BTreeMap::new(),
+    status_received: None,
+    fetched_at: None,
+    listening: false,
+    pending_host_key: None,
+    host_key_mismatch: false,
+    error: None,
+    failures: 0,
+    restarts: BTreeMap::new(),
+    counters_requested: None,
+    counters: BTreeMap::new(),
+    rates: BTreeMap::new(),
+    history: BTreeMap::new(),
+    sla: BTreeMap::new(),
+};
+
+/// Overall state shown by the panel icon, from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Health {
+    Up,
+    /// Waiting for the first status or for the interface to connect.
+    Pending,
+    /// The first interface is up, but another monitored interface or router is not.
+    Degraded,
+    /// Refreshes fail, the status shown is the last one received.
+    Stale,
+    Down,
+    /// The router can't be reached or refuses the applet.
+    Error,
+}
+
+impl Health {
+    fn icon_name(self) -> &'static str {
+        match self {
+            Self::Up => "com.github.mushonnip.OpenwrtInterfaceStatus-up-symbolic",
+            Self::Pending => "com.github.mushonnip.OpenwrtInterfaceStatus-pending-symbolic",
+            Self::Degraded => "com.github.mushonnip.OpenwrtInterfaceStatus-degraded-symbolic",
+            Self::Stale => "com.github.mushonnip.OpenwrtInterfaceStatus-stale-symbolic",
+            Self::Down => "com.github.mushonnip.OpenwrtInterfaceStatus-down-symbolic",
+            Self::Error => "com.github.mushonnip.OpenwrtInterfaceStatus-error-symbolic",
+        }
+    }
+
+    /// What screen readers announce for the panel button, e.g. `wan up`.
+    fn label(self, subject: &str) -> String {
+        match self {
+            Self::Up => format!("{} up", subject),
+            Self::Pending => format!("{} connecting", subject),
+            Self::Degraded => format!("{} partly down", subject),
+            Self::Stale => format!("{} status outdated", subject),
+            Self::Down => format!("{} down", subject),
+            Self::Error => format!("{} failing", subject),
+        }
+    }
+}
+
+/// Pages that can be shown in the popup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PopupPage {
+    #[default]
+    Status,
+    Settings,
+}
+
+/// Time spans the traffic graphs can cover.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryWindow {
+    FiveMinutes,
+    #[default]
+    Hour,
+    Day,
+}
+
+impl HistoryWindow {
+    const ALL: [HistoryWindow; 3] = [Self::FiveMinutes, Self::Hour, Self::Day];
+
+    fn duration(self) -> Duration {
+        match self {
+            Self::FiveMinutes => Duration::from_secs(5 * 60),
+            Self::Hour => Duration::from_secs(60 * 60),
+            Self::Day => Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::FiveMinutes => "5m",
+            Self::Hour => "1h",
+            Self::Day => "24h",
+        }
+    }
 }
 
 /// Messages emitted by the application and its widgets.
+///
+/// Messages about a single router carry its profile name first.
 #[derive(Debug, Clone)]
 pub enum Message {
     TogglePopup,
     PopupClosed(Id),
     UpdateConfig(Config),
-    UpdateInterfaceStatus(Result<InterfaceStatus, String>),
+    UpdateInterfaceStatus(String, Result<BTreeMap<String, InterfaceStatus>, AppError>),
     Tick,
-    RestartInterface,
+    TrafficTick,
+    UpdateCounters(String, Result<BTreeMap<String, DeviceCounters>, AppError>),
+    ShowRatesInPanel(bool),
+    ShowNotifications(ChangeKind, bool),
+    PanelTemplate(String),
+    PanelIconOnly(bool),
+    ShowHistory(HistoryWindow),
+    Refresh,
+    Poll(String),
+    RestartInterface(String, String),
+    InterfaceRestarted(
+        String,
+        String,
+        Result<(Duration, InterfaceStatus), AppError>,
+    ),
+    ShowPage(PopupPage),
+    EditRouter(Option<String>),
+    SettingsInput(SettingsField, String),
+    SettingsTransport(Transport),
+    SettingsScheme(HttpScheme),
+    SaveSettings,
+    RemoveRouter,
+    TestConnection,
+    ConnectionTested(Result<String, AppError>),
+    RouterDiscovered(String, Option<OpenWrtConfig>),
+    SettingsDiscovered(Option<OpenWrtConfig>),
+    HostKeyScanned(String, Result<HostKey, AppError>),
+    TrustHostKey(String),
+    ForgetHostKey(String),
+    PasswordsLoaded(BTreeMap<String, String>),
+    Listening(String, bool),
+    ClockTick,
 }
 
 /// Create a COSMIC application from the app model
@@ -65,25 +305,54 @@ impl cosmic::Application for AppModel {
         core: cosmic::Core,
         _flags: Self::Flags,
     ) -> (Self, Task<cosmic::Action<Self::Message>>) {
+        let config_handler = cosmic_config::Config::new(Self::APP_ID, Config::VERSION).ok();
+        let config = config_handler
+            .as_ref()
+            .map(|context| match Config::get_entry(context) {
+                Ok(config) => config,
+                Err((_errors, config)) => {
+                    // for why in errors {
+                    //     tracing::error!(%why, "error loading app config");
+                    // }
+
+                    config
+                }
+            })
+            .unwrap_or_default();
+
+        let usage_path = usage::usage_path(Self::APP_ID);
+        let usage = usage_path
+            .as_deref()
+            .map(UsageStore::load)
+            .unwrap_or_default();
+
+        // Passwords live in the keyring, routers logging in with one wait until it was read
+        let names = config
+            .routers
+            .iter()
+            .map(|profile| profile.name.clone())
+            .collect();
+        let load_passwords = Task::perform(secrets::load_passwords(names), |result| {
+            let passwords = result.unwrap_or_else(|why| {
+                eprintln!("Error reading router passwords: {}", why);
+                BTreeMap::new()
+            });
+            cosmic::Action::App(Message::PasswordsLoaded(passwords))
+        });
+
         // Construct the app model with the runtime's core.
         let app = AppModel {
             core,
-            config: cosmic_config::Config::new(Self::APP_ID, Config::VERSION)
-                .map(|context| match Config::get_entry(&context) {
-                    Ok(config) => config,
-                    Err((_errors, config)) => {
-                        // for why in errors {
-                        //     tracing::error!(%why, "error loading app config");
-                        // }
-
-                        config
-                    }
-                })
-                .unwrap_or_default(),
+            config_handler,
+            panel_template: config.panel_template.clone(),
+            config,
+            usage,
+            usage_path,
+            journal: Journal::load(journal::journal_path(Self::APP_ID)),
             ..Default::default()
         };
 
-        (app, Task::none())
+        (app, load_passwords)
     }
 
     fn on_close_requested(&self, id: Id) -> Option<Message> {
@@ -95,50 +364,71 @@ impl cosmic::Application for AppModel {
     /// Application events will be processed through the view. Any messages emitted by
     /// events received by widgets will be passed to the update method.
     fn view(&self) -> Element<'_, Self::Message> {
-        let status = match &self.interface_status {
-            Some(status) => status.format_uptime(),
-            None => String::from("No interface"),
+        let health = self.panel_health();
+        let label = health.label(&self.panel_subject(health));
+        if self.config.panel_icon_only {
+            return self
+                .core
+                .applet
+                .icon_button(health.icon_name())
+                .name(label)
+                .on_press_down(Message::TogglePopup)
+                .into();
+        }
+
+        let template = self
+            .config
+            .panel_template
+            .parse::<PanelTemplate>()
+            .unwrap_or_default();
+        let status = match self.config.routers.as_slice() {
+            [profile] => self.panel_text(profile, &template),
+            // Without a template of their own several routers are summed up by counting
+            // the reachable ones whose first interface is up
+            profiles if self.config.panel_template == panel::DEFAULT_TEMPLATE => {
+                let up = profiles
+                    .iter()
+                    .filter(|profile| self.router_up(profile))
+                    .count();
+                if up < profiles.len() {
+                    format!("⚠ {}/{} up", up, profiles.len())
+                } else {
+                    format!("{}/{} up", up, profiles.len())
+                }
+            }
+            profiles => profiles
+                .iter()
+                .map(|profile| self.panel_text(profile, &template))
+                .collect::<Vec<_>>()
+                .join(" · "),
         };
 
-        let st = cosmic::iced_widget::row![cosmic::iced_widget::text(status)];
+        let icon = widget::icon::from_name(health.icon_name())
+            .size(16)
+            .symbolic(true);
+        let name = format!("{}, {}", label, status);
+        let st = widget::row()
+            .spacing(4)
+            .align_y(cosmic::iced::Alignment::Center)
+            .push(icon)
+            .push(cosmic::iced_widget::text(status));
 
         let data = cosmic::Element::from(st);
         let button = cosmic::widget::button::custom(data)
             .class(cosmic::theme::Button::AppletIcon)
+            .name(name)
             .on_press_down(Message::TogglePopup);
 
         cosmic::widget::autosize::autosize(button, cosmic::widget::Id::unique()).into()
     }
 
     fn view_window(&self, _id: Id) -> Element<'_, Self::Message> {
-        let ip_text = self
-            .interface_status
-            .as_ref()
-            .and_then(|status| status.ipv4_address.get(0))
-            .map(|addr| widget::text(addr.address.clone()))
-            .unwrap_or_else(|| widget::text("N/A"));
-
-        let restart_button = button(widget::text("Restart Interface"))
-            .on_press(Message::RestartInterface)
-            .padding(8);
-
-        let refresh_button = button(widget::text("Refresh IP"))
-            .on_press(Message::Tick)
-            .padding(8);
-
-        // Place both buttons in a row
-        let button_row = widget::row()
-            .spacing(10) // space between buttons
-            .push(restart_button)
-            .push(refresh_button);
-
-        let content_list = widget::list_column()
-            .padding(5)
-            .spacing(10)
-            .add(widget::settings::item("IP", ip_text))
-            .add(button_row); // Add the row of buttons instead of a single button
+        let content = match self.page {
+            PopupPage::Status => self.view_status(),
+            PopupPage::Settings => self.view_settings(),
+        };
 
-        self.core.applet.popup_container(content_list).into()
+        self.core.applet.popup_container(content).into()
     }
 
     /// Register subscriptions for this application.
@@ -148,16 +438,47 @@ impl cosmic::Application for AppModel {
     /// beginning of the application, and persist through its lifetime.
     fn subscription(&self) -> Subscription<Self::Message> {
         struct TickerSubscription;
+        struct ClockSubscription;
+        struct EventSubscription;
+        struct TrafficSubscription;
+
+        // Poll only while some router has no event stream reporting changes as they
+        // happen, otherwise just redraw so the shown uptime keeps counting.
+        let listening = self
+            .config
+            .routers
+            .iter()
+            .all(|profile| self.state(&profile.name).listening);
+        let (timer_id, timer_message) = if listening {
+            (
+                std::any::TypeId::of::<ClockSubscription>(),
+                Message::ClockTick,
+            )
+        } else {
+            (std::any::TypeId::of::<TickerSubscription>(), Message::Tick)
+        };
 
-        Subscription::batch(vec![
+        let mut subscriptions = vec![
             Subscription::run_with_id(
-                std::any::TypeId::of::<TickerSubscription>(),
+                timer_id,
                 cosmic::iced::stream::channel(4, move |mut channel| async move {
                     loop {
-                        if channel.send(Message::Tick).await.is_err() {
+                        if channel.send(timer_message.clone()).await.is_err() {
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+                    }
+                    futures_util::future::pending().await
+                }),
+            ),
+            Subscription::run_with_id(
+                std::any::TypeId::of::<TrafficSubscription>(),
+                cosmic::iced::stream::channel(4, |mut channel| async move {
+                    loop {
+                        if channel.send(Message::TrafficTick).await.is_err() {
                             break;
                         }
-                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        tokio::time::sleep(Duration::from_secs(TRAFFIC_INTERVAL_SECS)).await;
                     }
                     futures_util::future::pending().await
                 }),
@@ -172,7 +493,70 @@ impl cosmic::Application for AppModel {
 
                     Message::UpdateConfig(update.config)
                 }),
-        ])
+        ];
+
+        // Push status changes as soon as netifd reports them, restarting with new settings
+        for profile in &self.config.routers {
+            let RouterProfile { name, router, .. } = profile.clone();
+            if !router.transport.uses_ssh() || router.host_key.is_none() {
+                continue;
+            }
+            // Only HTTP logs in with the password, keep it out of the subscription id
+            let router = OpenWrtConfig {
+                password: String::new(),
+                ..router
+            };
+            subscriptions.push(Subscription::run_with_id(
+                (
+                    std::any::TypeId::of::<EventSubscription>(),
+                    name.clone(),
+                    router.clone(),
+                ),
+                cosmic::iced::stream::channel(4, move |mut channel| async move {
+                    let transport = AnyTransport::new(&router);
+                    loop {
+                        match checker::status::listen_interface_events(&transport).await {
+                            Ok(mut events) => {
+                                let message = Message::Listening(name.clone(), true);
+                                if channel.send(message).await.is_err() {
+                                    return;
+                                }
+                                while let Some(event) = events.next().await {
+                                    if !checker::status::is_status_event(&event, &router.interfaces)
+                                    {
+                                        continue;
+                                    }
+                                    let result = checker::status::fetch_interface_statuses(
+                                        &transport,
+                                        &router.interfaces,
+                                    )
+                                    .await;
+                                    if channel
+                                        .send(Message::UpdateInterfaceStatus(name.clone(), result))
+                                        .await
+                                        .is_err()
+                                    {
+                                        return;
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("Error listening for interface events on {}: {}", name, e)
+                            }
+                        }
+
+                        // Fall back to polling until the stream can be reopened
+                        let message = Message::Listening(name.clone(), false);
+                        if channel.send(message).await.is_err() {
+                            return;
+                        }
+                        tokio::time::sleep(Duration::from_secs(EVENT_RETRY_SECS)).await;
+                    }
+                }),
+            ));
+        }
+
+        Subscription::batch(subscriptions)
     }
 
     /// Handles messages emitted by the application and its widgets.
@@ -181,13 +565,40 @@ impl cosmic::Application for AppModel {
     /// on the application's async runtime.
     fn update(&mut self, message: Self::Message) -> Task<cosmic::Action<Self::Message>> {
         match message {
-            Message::UpdateConfig(config) => {
+            Message::UpdateConfig(mut config) => {
+                // The config holds no passwords, keep those read from the keyring
+                for profile in &mut config.routers {
+                    if let Some(current) = self.config.profile(&profile.name) {
+                        profile.router.password = current.router.password.clone();
+                    }
+                }
+                self.forget_changed(&config.routers);
                 self.config = config;
+                // Keep a half-typed template, but show one changed elsewhere
+                if self.panel_template.parse::<PanelTemplate>().is_ok() {
+                    self.panel_template = self.config.panel_template.clone();
+                }
+                // The profile being edited was removed elsewhere
+                if let Some(name) = &self.editing {
+                    if self.config.profile(name).is_none() {
+                        self.editing = None;
+                        self.page = PopupPage::Status;
+                    }
+                }
+                // Names taken by other routers may have changed
+                self.validate_settings();
             }
             Message::TogglePopup => {
                 return if let Some(p) = self.popup.take() {
                     destroy_popup(p)
                 } else {
+                    self.page = PopupPage::Status;
+                    // Outcomes of earlier restarts have been seen by now
+                    for state in self.routers.values_mut() {
+                        state
+                            .restarts
+                            .retain(|_, restart| matches!(restart, Restart::Running));
+                    }
                     let new_id = Id::unique();
                     self.popup.replace(new_id);
                     let mut popup_settings = self.core.applet.get_popup_settings(
@@ -203,40 +614,510 @@ impl cosmic::Application for AppModel {
                         .min_height(200.0)
                         .max_height(1080.0);
                     get_popup(popup_settings)
-                }
+                };
             }
             Message::PopupClosed(id) => {
                 if self.popup.as_ref() == Some(&id) {
                     self.popup = None;
                 }
             }
-            Message::UpdateInterfaceStatus(result) => {
+            Message::UpdateInterfaceStatus(name, result) => {
+                let notifications = match &result {
+                    Ok(statuses) => self.track_changes(&name, statuses),
+                    Err(_) => Task::none(),
+                };
+
+                let state = self.state_mut(&name);
                 match result {
-                    Ok(status) => {
-                        self.interface_status = Some(status);
+                    Ok(statuses) => {
+                        state.interface_statuses = statuses;
+                        state.status_received = Some(Instant::now());
+                        state.fetched_at = Some(Local::now());
+                        state.host_key_mismatch = false;
+                        state.error = None;
+                        state.failures = 0;
+                        self.update_sla(&name);
                     }
                     Err(e) => {
-                        eprintln!("Error updating interface status: {}", e);
-                        self.interface_status = None; // or leave unchanged: `self.interface_status = None;` is optional
+                        eprintln!("Error updating interface status of {}: {}", name, e);
+                        state.host_key_mismatch = e == AppError::HostKeyMismatch;
+                        state.record_failure(e);
                     }
                 }
+                return notifications;
             }
             Message::Tick => {
-                // Trigger interface status update every 5 seconds
+                self.update_all_sla();
+                // Routers with an open event stream are updated as events arrive
+                let tasks: Vec<_> = self
+                    .config
+                    .routers
+                    .iter()
+                    .filter(|profile| !self.state(&profile.name).listening)
+                    .map(|profile| self.poll(profile))
+                    .collect();
+                return Task::batch(tasks);
+            }
+            Message::Refresh => {
+                let tasks: Vec<_> = self
+                    .config
+                    .routers
+                    .iter()
+                    .map(|profile| self.poll(profile))
+                    .collect();
+                return Task::batch(tasks);
+            }
+            Message::Poll(name) => {
+                if let Some(profile) = self.config.profile(&name) {
+                    return self.poll(profile);
+                }
+            }
+            Message::RestartInterface(name, interface) => {
+                let Some(profile) = self.config.profile(&name) else {
+                    return Task::none();
+                };
+                let router = profile.router.clone();
+                self.state_mut(&name)
+                    .restarts
+                    .insert(interface.clone(), Restart::Running);
+
+                let restarted = interface.clone();
                 return Task::perform(
-                    async {
-                        match checker::status::fetch_interface_status().await {
-                            Ok(status) => Ok(status),
-                            Err(e) => Err(e.to_string()), // or format!("{:?}", e) if e doesn't impl Display
-                        }
+                    async move {
+                        let transport = AnyTransport::new(&router);
+                        checker::status::restart_interface(&transport, &restarted).await?;
+                        checker::status::wait_until_up(
+                            &transport,
+                            &restarted,
+                            Duration::from_secs(RESTART_POLL_SECS),
+                            Duration::from_secs(RESTART_TIMEOUT_SECS),
+                        )
+                        .await
+                    },
+                    move |result| {
+                        cosmic::Action::App(Message::InterfaceRestarted(
+                            name.clone(),
+                            interface.clone(),
+                            result,
+                        ))
                     },
-                    |result| cosmic::Action::App(Message::UpdateInterfaceStatus(result)),
                 );
             }
-            Message::RestartInterface => {
-                return Task::perform(async { checker::status::restart_interface().await }, |_| {
-                    cosmic::Action::App(Message::Tick)
+            Message::InterfaceRestarted(name, interface, result) => {
+                let (restart, detail) = match result {
+                    Ok((took, status)) => {
+                        // Later statuses are compared with the one after the restart, so
+                        // coming back up isn't reported as a change
+                        self.state_mut(&name)
+                            .interface_statuses
+                            .insert(interface.clone(), status);
+                        let detail = format!(
+                            "Back up after {}",
+                            checker::status::format_duration(took.as_secs())
+                        );
+                        (Restart::Done(took), detail)
+                    }
+                    Err(e) => {
+                        eprintln!("Error restarting {} on {}: {}", interface, name, e);
+                        let detail = format!("Failed: {}", e);
+                        (Restart::Failed(e), detail)
+                    }
+                };
+                let entry = JournalEntry {
+                    at: Local::now(),
+                    router: name.clone(),
+                    interface: interface.clone(),
+                    event: JournalEvent::Restarted,
+                    detail,
+                };
+                if let Err(why) = self.journal.record(entry) {
+                    eprintln!("Error writing the event journal: {}", why);
+                }
+                self.update_sla(&name);
+                self.state_mut(&name).restarts.insert(interface, restart);
+                return Task::done(cosmic::Action::App(Message::Poll(name)));
+            }
+            Message::ShowPage(page) => {
+                self.page = page;
+            }
+            Message::EditRouter(name) => {
+                let profile = match &name {
+                    Some(name) => self.config.profile(name).cloned().unwrap_or_default(),
+                    None => RouterProfile {
+                        name: self.unused_name(),
+                        ..RouterProfile::default()
+                    },
+                };
+                self.settings = SettingsForm::from_profile(&profile);
+                self.editing = name;
+                self.validate_settings();
+                self.connection_test = None;
+                self.page = PopupPage::Settings;
+
+                // Suggest the default gateway until a host is entered
+                if self.settings.host.is_empty() {
+                    return Task::perform(checker::discovery::discover_router(), |found| {
+                        cosmic::Action::App(Message::SettingsDiscovered(found))
+                    });
+                }
+            }
+            Message::SettingsInput(field, value) => {
+                self.settings.set(field, value);
+                self.validate_settings();
+                self.connection_test = None;
+            }
+            Message::SettingsTransport(transport) => {
+                // Follow the transport's usual port unless a custom one was entered
+                let previous = self.settings.default_port();
+                self.settings.transport = transport;
+                if self.settings.port == previous.to_string() {
+                    self.settings.port = self.settings.default_port().to_string();
+                }
+                self.validate_settings();
+                self.connection_test = None;
+            }
+            Message::SettingsScheme(scheme) => {
+                let previous = self.settings.default_port();
+                self.settings.scheme = scheme;
+                if self.settings.port == previous.to_string() {
+                    self.settings.port = self.settings.default_port().to_string();
+                }
+                self.validate_settings();
+                self.connection_test = None;
+            }
+            Message::SaveSettings => {
+                let Ok(mut profile) = self.settings.validate(&self.taken_names()) else {
+                    return Task::none();
+                };
+                profile.router.host_key = self.pinned_host_key(&profile.router);
+                let previous = self
+                    .editing
+                    .as_deref()
+                    .and_then(|name| self.config.profile(name))
+                    .map(|previous| (previous.name.clone(), previous.router.password.clone()));
+
+                let mut routers = self.config.routers.clone();
+                let position = self.editing.as_ref().and_then(|editing| {
+                    routers
+                        .iter()
+                        .position(|existing| existing.name == *editing)
                 });
+                match position {
+                    Some(index) => routers[index] = profile.clone(),
+                    None => routers.push(profile.clone()),
+                }
+
+                if !self.save_routers(routers) {
+                    return Task::none();
+                }
+
+                // The keyring holds passwords by profile name, move it along on a rename
+                let mut tasks = Vec::new();
+                let renamed = previous.as_ref().filter(|(name, _)| *name != profile.name);
+                if let Some((name, _)) = renamed {
+                    tasks.push(store_password(name.clone(), String::new()));
+                }
+                let stored = match (&previous, renamed) {
+                    (Some((_, password)), None) => password.as_str(),
+                    _ => "",
+                };
+                if profile.router.password != stored {
+                    tasks.push(store_password(
+                        profile.name.clone(),
+                        profile.router.password.clone(),
+                    ));
+                }
+
+                self.editing = None;
+                self.page = PopupPage::Status;
+                tasks.push(Task::done(cosmic::Action::App(Message::Poll(profile.name))));
+                return Task::batch(tasks);
+            }
+            Message::RemoveRouter => {
+                let Some(editing) = self.editing.clone() else {
+                    return Task::none();
+                };
+                let mut routers = self.config.routers.clone();
+                routers.retain(|profile| profile.name != editing);
+                if routers.is_empty() || !self.save_routers(routers) {
+                    return Task::none();
+                }
+
+                self.editing = None;
+                self.page = PopupPage::Status;
+                return store_password(editing, String::new());
+            }
+            Message::TestConnection => {
+                let Ok(profile) = self.settings.validate(&self.taken_names()) else {
+                    return Task::none();
+                };
+                let mut router = profile.router;
+                router.host_key = self.pinned_host_key(&router);
+
+                self.testing_connection = true;
+                return Task::perform(
+                    async move {
+                        // Without a trusted key only report what the router offers
+                        if router.transport.uses_ssh() && router.host_key.is_none() {
+                            return checker::host_key::scan_host_key(&router).await.map(|key| {
+                                format!("Reachable, host key {} not trusted yet", key.fingerprint)
+                            });
+                        }
+
+                        let transport = AnyTransport::new(&router);
+                        checker::status::fetch_interface_statuses(&transport, &router.interfaces)
+                            .await
+                            .map(|statuses| {
+                                let states: Vec<String> = router
+                                    .interfaces
+                                    .iter()
+                                    .map(|name| match statuses.get(name) {
+                                        Some(status) if status.up => format!("{} up", name),
+                                        Some(_) => format!("{} down", name),
+                                        None => format!("{} not found", name),
+                                    })
+                                    .collect();
+                                format!("Connected: {}", states.join(", "))
+                            })
+                    },
+                    |result| cosmic::Action::App(Message::ConnectionTested(result)),
+                );
+            }
+            Message::ConnectionTested(result) => {
+                self.testing_connection = false;
+                self.connection_test = Some(result);
+            }
+            Message::RouterDiscovered(name, found) => {
+                let Some(found) = found else {
+                    eprintln!("No router found at the default gateway for {}", name);
+                    return Task::none();
+                };
+
+                // Only fill in routers that still have no host, the user may have been faster
+                let mut routers = self.config.routers.clone();
+                let Some(profile) = routers
+                    .iter_mut()
+                    .find(|profile| profile.name == name && profile.router.host.is_empty())
+                else {
+                    return Task::none();
+                };
+                profile.router.host = found.host;
+                profile.router.port = found.port;
+                profile.router.transport = found.transport;
+
+                if self.save_routers(routers) {
+                    return Task::done(cosmic::Action::App(Message::Poll(name)));
+                }
+            }
+            Message::SettingsDiscovered(found) => {
+                if let Some(found) = found {
+                    if self.page == PopupPage::Settings && self.settings.host.trim().is_empty() {
+                        self.settings.host = found.host;
+                        self.settings.port = found.port.to_string();
+                        self.settings.transport = found.transport;
+                        self.validate_settings();
+                    }
+                }
+            }
+            Message::HostKeyScanned(name, result) => {
+                let state = self.state_mut(&name);
+                match result {
+                    Ok(key) => {
+                        state.pending_host_key = Some(key);
+                        state.error = None;
+                        state.failures = 0;
+                    }
+                    Err(e) => {
+                        eprintln!("Error reading host key of {}: {}", name, e);
+                        state.record_failure(e);
+                    }
+                }
+            }
+            Message::TrustHostKey(name) => {
+                let Some(key) = self.state_mut(&name).pending_host_key.take() else {
+                    return Task::none();
+                };
+
+                let routers = self.with_host_key(&name, Some(key));
+                if self.save_routers(routers) {
+                    return Task::done(cosmic::Action::App(Message::Poll(name)));
+                }
+            }
+            Message::ForgetHostKey(name) => {
+                let routers = self.with_host_key(&name, None);
+                if self.save_routers(routers) {
+                    self.state_mut(&name).host_key_mismatch = false;
+                    return Task::done(cosmic::Action::App(Message::Poll(name)));
+                }
+            }
+            Message::PasswordsLoaded(mut passwords) => {
+                for profile in &mut self.config.routers {
+                    if let Some(password) = passwords.remove(&profile.name) {
+                        profile.router.password = password;
+                    }
+                }
+                self.passwords_loaded = true;
+
+                let tasks: Vec<_> = self
+                    .config
+                    .routers
+                    .iter()
+                    .filter(|profile| profile.router.transport == Transport::HttpUbus)
+                    .map(|profile| self.poll(profile))
+                    .collect();
+                return Task::batch(tasks);
+            }
+            Message::Listening(name, listening) => {
+                self.state_mut(&name).listening = listening;
+            }
+            Message::ClockTick => {
+                // The view recomputes the running uptime, the availability moves on too
+                self.update_all_sla();
+            }
+            Message::TrafficTick => {
+                // Spawning `ssh` for every read is too costly for rates nobody sees, so
+                // those routers only keep usage and history going at the polling pace
+                let rates_shown = self.rates_on_screen();
+                let profiles: Vec<_> = self
+                    .config
+                    .routers
+                    .iter()
+                    .filter(|profile| {
+                        // Routers that can't be polled yet have no counters to read either
+                        let router = &profile.router;
+                        !router.host.is_empty()
+                            && (!router.transport.uses_ssh() || router.host_key.is_some())
+                            && (router.transport != Transport::HttpUbus || self.passwords_loaded)
+                    })
+                    .filter(|profile| {
+                        rates_shown
+                            || profile.router.transport != Transport::SshCommand
+                            || self
+                                .state(&profile.name)
+                                .counters_requested
+                                .is_none_or(|at| {
+                                    at.elapsed() >= Duration::from_secs(POLL_INTERVAL_SECS)
+                                })
+                    })
+                    .cloned()
+                    .collect();
+                let tasks: Vec<_> = profiles
+                    .into_iter()
+                    .map(|RouterProfile { name, router, .. }| {
+                        self.state_mut(&name).counters_requested = Some(Instant::now());
+                        Task::perform(
+                            async move {
+                                let transport = AnyTransport::new(&router);
+                                checker::traffic::fetch_device_counters(&transport).await
+                            },
+                            move |result| {
+                                cosmic::Action::App(Message::UpdateCounters(name.clone(), result))
+                            },
+                        )
+                    })
+                    .collect();
+                return Task::batch(tasks);
+            }
+            Message::UpdateCounters(name, result) => {
+                let counters = match result {
+                    Ok(counters) => counters,
+                    Err(e) => {
+                        eprintln!("Error reading traffic counters of {}: {}", name, e);
+                        return Task::none();
+                    }
+                };
+
+                let alerts = self.record_usage(&name, &counters);
+
+                let state = self.state_mut(&name);
+                let at = Instant::now();
+                let mut rates = BTreeMap::new();
+                for (device, counters) in &counters {
+                    let sample = CounterSample {
+                        counters: *counters,
+                        at,
+                    };
+                    let previous = state.counters.get(device);
+                    if let Some(rate) = previous
+                        .and_then(|previous| checker::traffic::throughput(previous, &sample))
+                    {
+                        rates.insert(device.clone(), rate);
+                    }
+                }
+                state.rates = rates;
+
+                // Interfaces that are up only get a sample once there is a rate to record
+                for (interface, status) in &state.interface_statuses {
+                    let rate = status
+                        .l3_device
+                        .as_ref()
+                        .and_then(|device| state.rates.get(device));
+                    if status.up && rate.is_none() {
+                        continue;
+                    }
+                    let sample = Sample {
+                        at,
+                        up: status.up,
+                        rx_bytes_per_sec: rate.map_or(0.0, |rate| rate.rx_bytes_per_sec),
+                        tx_bytes_per_sec: rate.map_or(0.0, |rate| rate.tx_bytes_per_sec),
+                    };
+                    state
+                        .history
+                        .entry(interface.clone())
+                        .or_default()
+                        .push(sample);
+                }
+
+                state.counters = counters
+                    .into_iter()
+                    .map(|(device, counters)| (device, CounterSample { counters, at }))
+                    .collect();
+                return alerts;
+            }
+            Message::ShowHistory(window) => {
+                self.history_window = window;
+            }
+            Message::ShowRatesInPanel(show) => {
+                if let Some(handler) = &self.config_handler {
+                    if let Err(why) = self.config.set_show_rates_in_panel(handler, show) {
+                        eprintln!("Error saving settings: {}", why);
+                    }
+                } else {
+                    self.config.show_rates_in_panel = show;
+                }
+            }
+            Message::PanelTemplate(template) => {
+                if template.parse::<PanelTemplate>().is_ok() {
+                    if let Some(handler) = &self.config_handler {
+                        if let Err(why) = self.config.set_panel_template(handler, template.clone())
+                        {
+                            eprintln!("Error saving settings: {}", why);
+                        }
+                    } else {
+                        self.config.panel_template = template.clone();
+                    }
+                }
+                self.panel_template = template;
+            }
+            Message::PanelIconOnly(icon_only) => {
+                if let Some(handler) = &self.config_handler {
+                    if let Err(why) = self.config.set_panel_icon_only(handler, icon_only) {
+                        eprintln!("Error saving settings: {}", why);
+                    }
+                } else {
+                    self.config.panel_icon_only = icon_only;
+                }
+            }
+            Message::ShowNotifications(kind, show) => {
+                let mut notifications = self.config.notifications;
+                notifications.set(kind, show);
+                if let Some(handler) = &self.config_handler {
+                    if let Err(why) = self.config.set_notifications(handler, notifications) {
+                        eprintln!("Error saving settings: {}", why);
+                    }
+                } else {
+                    self.config.notifications = notifications;
+                }
             }
         }
         Task::none()
@@ -246,3 +1127,1127 @@ impl cosmic::Application for AppModel {
         Some(cosmic::applet::style())
     }
 }
+
+impl RouterState {
+    fn clear_statuses(&mut self) {
+        self.interface_statuses.clear();
+        self.status_received = None;
+        self.fetched_at = None;
+    }
+
+    /// Keeps the last good statuses around, they only go stale.
+    fn record_failure(&mut self, error: AppError) {
+        self.error = Some(error);
+        self.failures += 1;
+    }
+
+    /// Local time of the last successful fetch while the following ones fail.
+    fn stale_since(&self) -> Option<String> {
+        self.error.as_ref()?;
+        self.fetched_at.map(|at| at.format("%H:%M").to_string())
+    }
+
+    /// Short panel text for why a router keeps failing, `None` while fetches succeed.
+    fn failure_label(&self) -> Option<&'static str> {
+        if self.host_key_mismatch {
+            return Some("Host key changed");
+        }
+        if self.failures < FAILURES_UNTIL_UNREACHABLE {
+            return None;
+        }
+        let label = match self.error.as_ref()? {
+            AppError::AuthFailed(_) => "Auth failed",
+            AppError::HostUnreachable(_) | AppError::Timeout(_) | AppError::Disconnected(_) => {
+                "Unreachable"
+            }
+            AppError::HostKeyUnknown => "Host key not trusted",
+            AppError::HostKeyMismatch => "Host key changed",
+            AppError::InterfaceNotFound(_) => "No such interface",
+            AppError::UbusUnavailable(_) => "No ubus",
+            AppError::PermissionDenied(_) => "Access denied",
+            AppError::LocalIo(_) => "Local error",
+            AppError::MalformedResponse(_) => "Bad reply",
+            AppError::Other(_) => "Error",
+        };
+        Some(label)
+    }
+
+    /// Current transfer rates of the device behind `status`.
+    fn throughput(&self, status: &InterfaceStatus) -> Option<&Throughput> {
+        status
+            .l3_device
+            .as_ref()
+            .and_then(|device| self.rates.get(device))
+    }
+
+    /// Uptime of `status`, counting on from when it was fetched while the interface is up.
+    /// Stale statuses keep the uptime they were fetched with, the link may be gone since.
+    fn uptime_text(&self, status: &InterfaceStatus) -> String {
+        if !status.up || self.error.is_some() {
+            return status.format_uptime();
+        }
+        let elapsed = self.status_received.map_or(0, |at| at.elapsed().as_secs());
+        checker::status::format_duration(status.uptime + elapsed)
+    }
+}
+
+impl AppModel {
+    fn state(&self, name: &str) -> &RouterState {
+        self.routers.get(name).unwrap_or(&NO_STATE)
+    }
+
+    fn state_mut(&mut self, name: &str) -> &mut RouterState {
+        self.routers.entry(name.to_string()).or_default()
+    }
+
+    /// Checks the settings form again after it or the other routers changed.
+    fn validate_settings(&mut self) {
+        self.settings_errors = self
+            .settings
+            .validate(&self.taken_names())
+            .err()
+            .unwrap_or_default();
+    }
+
+    /// Recomputes the availability figures of the monitored interfaces of router `name`.
+    fn update_sla(&mut self, name: &str) {
+        let Some(profile) = self.config.profile(name) else {
+            return;
+        };
+        let state = self.state(name);
+        let now = Local::now();
+        let sla = profile
+            .router
+            .interfaces
+            .iter()
+            .map(|interface| {
+                let up_now = state
+                    .interface_statuses
+                    .get(interface)
+                    .map(|status| status.up);
+                let stats = SlaWindow::ALL.map(|window| {
+                    let entries = self.journal.entries();
+                    SlaStats::compute(entries, name, interface, window, now, up_now)
+                });
+                (interface.clone(), stats)
+            })
+            .collect();
+        self.state_mut(name).sla = sla;
+    }
+
+    fn update_all_sla(&mut self) {
+        let names: Vec<_> = self
+            .config
+            .routers
+            .iter()
+            .map(|profile| profile.name.clone())
+            .collect();
+        for name in names {
+            self.update_sla(&name);
+        }
+    }
+
+    /// Fetch the statuses of one router, or its host key while none is trusted yet.
+    fn poll(&self, profile: &RouterProfile) -> Task<cosmic::Action<Message>> {
+        // Poll with the current settings so config changes apply on the next tick
+        let RouterProfile { name, router, .. } = profile.clone();
+
+        // Nothing to connect to yet, look for the router at the default gateway
+        if router.host.is_empty() {
+            return Task::perform(checker::discovery::discover_router(), move |found| {
+                cosmic::Action::App(Message::RouterDiscovered(name.clone(), found))
+            });
+        }
+
+        // Logging in without the password would only fail
+        if router.transport == Transport::HttpUbus && !self.passwords_loaded {
+            return Task::none();
+        }
+
+        // Trust on first use: fetch the key and wait for the user to confirm it
+        if router.transport.uses_ssh() && router.host_key.is_none() {
+            if self.state(&name).pending_host_key.is_some() {
+                return Task::none();
+            }
+            return Task::perform(
+                async move { checker::host_key::scan_host_key(&router).await },
+                move |result| cosmic::Action::App(Message::HostKeyScanned(name.clone(), result)),
+            );
+        }
+
+        Task::perform(
+            async move {
+                let transport = AnyTransport::new(&router);
+                checker::status::fetch_interface_statuses(&transport, &router.interfaces).await
+            },
+            move |result| cosmic::Action::App(Message::UpdateInterfaceStatus(name.clone(), result)),
+        )
+    }
+
+    /// Drops what is known about routers that were removed or point somewhere else in
+    /// `routers`.
+    fn forget_changed(&mut self, routers: &[RouterProfile]) {
+        self.routers
+            .retain(|name, _| routers.iter().any(|profile| profile.name == *name));
+        let configs: Vec<_> = routers.iter().map(|profile| &profile.router).collect();
+        AnyTransport::retain_sessions(&configs);
+
+        for profile in routers {
+            let Some(current) = self.config.profile(&profile.name) else {
+                continue;
+            };
+            let current = current.router.clone();
+            if current == profile.router {
+                continue;
+            }
+
+            let state = self.state_mut(&profile.name);
+            // A key offered by the previous host must not be trusted for the new one
+            if current.host != profile.router.host || current.port != profile.router.port {
+                state.pending_host_key = None;
+                state.clear_statuses();
+            }
+            state.host_key_mismatch = false;
+            state.error = None;
+            state.failures = 0;
+            // Poll until the event stream for the new settings is up
+            state.listening = false;
+        }
+    }
+
+    /// Persists new router profiles, returning whether they were applied.
+    fn save_routers(&mut self, routers: Vec<RouterProfile>) -> bool {
+        self.forget_changed(&routers);
+
+        if let Some(handler) = &self.config_handler {
+            if let Err(why) = self.config.set_routers(handler, routers) {
+                eprintln!("Error saving settings: {}", why);
+                return false;
+            }
+        } else {
+            self.config.routers = routers;
+        }
+        true
+    }
+
+    /// The configured routers with the host key of `name` replaced by `key`.
+    fn with_host_key(&self, name: &str, key: Option<HostKey>) -> Vec<RouterProfile> {
+        let mut routers = self.config.routers.clone();
+        if let Some(profile) = routers.iter_mut().find(|profile| profile.name == name) {
+            profile.router.host_key = key;
+        }
+        routers
+    }
+
+    /// The trusted host key of the edited profile, as long as `router` still points at the
+    /// same host and port.
+    fn pinned_host_key(&self, router: &OpenWrtConfig) -> Option<HostKey> {
+        let current = &self.config.profile(self.editing.as_deref()?)?.router;
+        (current.host == router.host && current.port == router.port)
+            .then(|| current.host_key.clone())
+            .flatten()
+    }
+
+    /// Names the profile in the settings form must not take.
+    fn taken_names(&self) -> Vec<&str> {
+        self.config
+            .routers
+            .iter()
+            .map(|profile| profile.name.as_str())
+            .filter(|name| Some(*name) != self.editing.as_deref())
+            .collect()
+    }
+
+    /// A name for a new profile that no existing one uses.
+    fn unused_name(&self) -> String {
+        (1..)
+            .map(|n| {
+                if n == 1 {
+                    String::from("Router")
+                } else {
+                    format!("Router {}", n)
+                }
+            })
+            .find(|name| self.config.profile(name).is_none())
+            .unwrap_or_default()
+    }
+
+    /// Whether the popup or the panel currently shows transfer rates.
+    fn rates_on_screen(&self) -> bool {
+        if self.popup.is_some() {
+            return true;
+        }
+        let template = self
+            .config
+            .panel_template
+            .parse::<PanelTemplate>()
+            .unwrap_or_default();
+        !self.config.panel_icon_only
+            && (self.config.show_rates_in_panel
+                || template.uses(Placeholder::RxRate)
+                || template.uses(Placeholder::TxRate))
+    }
+
+    /// Panel text of one router: its first interface rendered through `template`, or
+    /// why nothing can be shown.
+    fn panel_text(&self, profile: &RouterProfile, template: &PanelTemplate) -> String {
+        let state = self.state(&profile.name);
+        let interface = profile.router.interfaces.first();
+        let primary = interface.and_then(|name| state.interface_statuses.get(name));
+        let status = match (state.failure_label(), primary) {
+            (Some(label), _) => return String::from(label),
+            (None, Some(status)) => status,
+            (None, None) if state.status_received.is_none() => return String::from("No data"),
+            (None, None) => return String::from("No interface"),
+        };
+
+        let rate = state.throughput(status);
+        let mut text = template.render(|placeholder| match placeholder {
+            Placeholder::Uptime => Some(state.uptime_text(status)),
+            Placeholder::Ipv4 => status.primary_ipv4().map(|address| address.to_string()),
+            Placeholder::Ipv6 => status
+                .global_ipv6_addresses()
+                .next()
+                .map(|address| address.address.to_string()),
+            Placeholder::Proto => status.proto.clone(),
+            Placeholder::Device => status.l3_device.clone(),
+            Placeholder::RxRate => {
+                rate.map(|rate| checker::traffic::format_rate(rate.rx_bytes_per_sec))
+            }
+            Placeholder::TxRate => {
+                rate.map(|rate| checker::traffic::format_rate(rate.tx_bytes_per_sec))
+            }
+            Placeholder::Interface => interface.cloned(),
+            Placeholder::Router => Some(profile.name.clone()),
+        });
+
+        // Templates that place the rates themselves don't get them twice
+        let rates_shown = template.uses(Placeholder::RxRate) || template.uses(Placeholder::TxRate);
+        if state.error.is_some() {
+            text.push_str(" (stale)");
+        } else if let Some(rate) = rate.filter(|_| self.config.show_rates_in_panel && !rates_shown)
+        {
+            text.push_str(&format!(
+                " ↓{} ↑{}",
+                checker::traffic::format_rate(rate.rx_bytes_per_sec),
+                checker::traffic::format_rate(rate.tx_bytes_per_sec)
+            ));
+        }
+        text
+    }
+
+    /// Whether the router is reachable and its first interface up.
+    fn router_up(&self, profile: &RouterProfile) -> bool {
+        let state = self.state(&profile.name);
+        state.failure_label().is_none()
+            && profile
+                .router
+                .interfaces
+                .first()
+                .and_then(|name| state.interface_statuses.get(name))
+                .is_some_and(|status| status.up)
+    }
+
+    /// Health of one router, judged by its first interface and the last fetch.
+    fn router_health(&self, profile: &RouterProfile) -> Health {
+        let state = self.state(&profile.name);
+        if state.failure_label().is_some() {
+            return Health::Error;
+        }
+        if state.error.is_some() {
+            return Health::Stale;
+        }
+
+        let mut statuses = profile
+            .router
+            .interfaces
+            .iter()
+            .map(|name| state.interface_statuses.get(name));
+        match statuses.next().flatten() {
+            None if state.status_received.is_none() => Health::Pending,
+            None => Health::Down,
+            Some(status) if status.up => {
+                // Any other monitored interface that is missing, down or unavailable
+                if statuses.all(|status| status.is_some_and(|status| status.up)) {
+                    Health::Up
+                } else {
+                    Health::Degraded
+                }
+            }
+            Some(status) if status.pending && status.available => Health::Pending,
+            Some(_) => Health::Down,
+        }
+    }
+
+    /// Health shown in the panel, the worst of all routers. Some routers being down while
+    /// others are up counts as degraded.
+    fn panel_health(&self) -> Health {
+        let healths: Vec<Health> = self
+            .config
+            .routers
+            .iter()
+            .map(|profile| self.router_health(profile))
+            .collect();
+        let worst = healths.iter().copied().max().unwrap_or(Health::Pending);
+        if worst == Health::Down && healths.contains(&Health::Up) {
+            Health::Degraded
+        } else {
+            worst
+        }
+    }
+
+    /// What the panel's health is about: the interface when a single router watches one,
+    /// otherwise the router, or all routers together.
+    fn panel_subject(&self, health: Health) -> String {
+        match self.config.routers.as_slice() {
+            [profile] => match profile.router.interfaces.as_slice() {
+                [interface] if health != Health::Error => interface.clone(),
+                _ => profile.name.clone(),
+            },
+            _ => String::from("Routers"),
+        }
+    }
+
+    /// Journals and notifies about what changed between the known and the newly received
+    /// `statuses` of a router, except for interfaces the user is restarting.
+    fn track_changes(
+        &mut self,
+        router: &str,
+        statuses: &BTreeMap<String, InterfaceStatus>,
+    ) -> Task<cosmic::Action<Message>> {
+        // Borrowed through the field so the journal and limiter below stay mutable
+        let state = self.routers.get(router).unwrap_or(&NO_STATE);
+        let now = Instant::now();
+
+        let mut tasks = Vec::new();
+        for (interface, current) in statuses {
+            let Some(previous) = state.interface_statuses.get(interface) else {
+                // Nothing to compare with yet, so catch up on what changed while the
+                // interface wasn't watched
+                let Some((_, since)) = self
+                    .journal
+                    .last_state(router, interface)
+                    .filter(|(was_up, _)| *was_up != current.up)
+                else {
+                    continue;
+                };
+                let at = Local::now();
+                let entry = if current.up {
+                    // The uptime tells when it came up, though not before the last entry
+                    let uptime = i64::try_from(current.uptime)
+                        .ok()
+                        .and_then(chrono::TimeDelta::try_seconds)
+                        .unwrap_or(chrono::TimeDelta::zero());
+                    JournalEntry {
+                        at: (at - uptime).max(since),
+                        router: router.to_string(),
+                        interface: interface.clone(),
+                        event: JournalEvent::CameUp,
+                        detail: format!("Up for {} when first seen", current.format_uptime()),
+                    }
+                } else {
+                    JournalEntry {
+                        at,
+                        router: router.to_string(),
+                        interface: interface.clone(),
+                        event: JournalEvent::WentDown,
+                        detail: String::from("Down when first seen"),
+                    }
+                };
+                if let Err(why) = self.journal.record(entry) {
+                    eprintln!("Error writing the event journal: {}", why);
+                }
+                continue;
+            };
+            if matches!(state.restarts.get(interface), Some(Restart::Running)) {
+                continue;
+            }
+
+            let changes = notifications::status_changes(previous, current);
+            for change in &changes {
+                let entry = JournalEntry {
+                    at: Local::now(),
+                    router: router.to_string(),
+                    interface: interface.clone(),
+                    event: change.kind.journal_event(),
+                    detail: change.detail.clone(),
+                };
+                if let Err(why) = self.journal.record(entry) {
+                    eprintln!("Error writing the event journal: {}", why);
+                }
+            }
+
+            let changes: Vec<_> = changes
+                .into_iter()
+                .filter(|change| self.config.notifications.enabled(change.kind))
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+            let Some(suppressed) = self.notification_limiter.allow(router, interface, now) else {
+                continue;
+            };
+
+            let (summary, body) = notifications::describe(router, interface, &changes, suppressed);
+            tasks.push(Task::perform(
+                notifications::notify(summary, body),
+                |result| {
+                    if let Err(why) = result {
+                        eprintln!("Error showing notification: {}", why);
+                    }
+                    cosmic::Action::None
+                },
+            ));
+        }
+        Task::batch(tasks)
+    }
+
+    /// Adds the traffic of the router's interfaces to the usage totals and notifies about
+    /// quotas that crossed one of their alert thresholds.
+    fn record_usage(
+        &mut self,
+        router: &str,
+        counters: &BTreeMap<String, DeviceCounters>,
+    ) -> Task<cosmic::Action<Message>> {
+        let (Some(profile), Some(state)) = (self.config.profile(router), self.routers.get(router))
+        else {
+            return Task::none();
+        };
+        let now = Local::now();
+        let period_start = usage::period_start(now.date_naive(), profile.plan.billing_day);
+
+        let mut alerts = Vec::new();
+        for (interface, status) in &state.interface_statuses {
+            let Some((device, device_counters)) = status
+                .l3_device
+                .as_ref()
+                .and_then(|device| Some((device, counters.get(device)?)))
+            else {
+                continue;
+            };
+            let reading = usage::Reading {
+                device,
+                counters: *device_counters,
+                uptime: status.uptime,
+                at: now,
+            };
+            let usage = self.usage.record(router, interface, reading, period_start);
+
+            let Some(&quota) = profile.plan.quotas.get(interface) else {
+                continue;
+            };
+            let percent = usage.percent_of(quota);
+            let crossed = profile
+                .plan
+                .quota_alerts
+                .iter()
+                .copied()
+                .filter(|threshold| u64::from(*threshold) <= percent)
+                .max();
+            if let Some(threshold) = crossed.filter(|threshold| *threshold > usage.alerted_percent)
+            {
+                usage.alerted_percent = threshold;
+                let summary = if threshold >= 100 {
+                    format!("{} used up its data quota", interface)
+                } else {
+                    format!("{} used {}% of its data quota", interface, threshold)
+                };
+                let body = format!(
+                    "{} of {} on {} since {}",
+                    checker::traffic::format_bytes(usage.total()),
+                    checker::traffic::format_bytes(quota),
+                    router,
+                    usage.period_start.format("%-d %b")
+                );
+                alerts.push(Task::perform(
+                    notifications::notify(summary, body),
+                    |result| {
+                        if let Err(why) = result {
+                            eprintln!("Error showing notification: {}", why);
+                        }
+                        cosmic::Action::None
+                    },
+                ));
+            }
+        }
+
+        let due = self
+            .usage_saved
+            .is_none_or(|saved| saved.elapsed() >= Duration::from_secs(USAGE_SAVE_SECS));
+        if let Some(path) = self
+            .usage_path
+            .as_deref()
+            .filter(|_| due || !alerts.is_empty())
+        {
+            if let Err(why) = self.usage.save(path) {
+                eprintln!("Error saving data usage to {}: {}", path.display(), why);
+            }
+            self.usage_saved = Some(Instant::now());
+        }
+
+        Task::batch(alerts)
+    }
+
+    /// One monitored interface with its state indicator, uptime, address and restart action.
+    fn interface_row<'a>(&'a self, router: &'a str, name: &'a str) -> Element<'a, Message> {
+        let state = self.state(router);
+        let (indicator, details) = match state.interface_statuses.get(name) {
+            Some(status) if status.up => {
+                let ip = status
+                    .primary_ipv4()
+                    .map(|address| address.to_string())
+                    .unwrap_or_else(|| String::from("N/A"));
+                ("●", format!("Up {} · {}", state.uptime_text(status), ip))
+            }
+            Some(status) if status.pending => ("◐", String::from("Connecting")),
+            Some(_) => ("○", String::from("Down")),
+            None if state.status_received.is_none() => ("○", String::from("No data")),
+            None => ("?", String::from("Not found on the router")),
+        };
+
+        let restart = state.restarts.get(name);
+        let running = matches!(restart, Some(Restart::Running));
+        let restart_button = button(widget::text(if running {
+            "Restarting…"
+        } else {
+            "Restart"
+        }))
+        .on_press_maybe(
+            (!running).then(|| Message::RestartInterface(router.to_string(), name.to_string())),
+        )
+        .padding(8);
+
+        let mut column = widget::column()
+            .width(Length::Fill)
+            .push(widget::text::heading(name))
+            .push(widget::text::caption(details));
+        if let Some(status) = state
+            .interface_statuses
+            .get(name)
+            .filter(|status| status.up)
+        {
+            for address in status.global_ipv6_addresses() {
+                column = column.push(widget::text::caption(format!(
+                    "IPv6 {}/{}",
+                    address.address, address.mask
+                )));
+            }
+            for prefix in &status.ipv6_prefix {
+                column = column.push(widget::text::caption(format!(
+                    "Delegated prefix {}/{}",
+                    prefix.address, prefix.mask
+                )));
+            }
+            if let Some(rate) = state.throughput(status) {
+                column = column.push(widget::text::caption(format!(
+                    "↓ {} · ↑ {} · {:.0}/{:.0} packets/s",
+                    checker::traffic::format_rate(rate.rx_bytes_per_sec),
+                    checker::traffic::format_rate(rate.tx_bytes_per_sec),
+                    rate.rx_packets_per_sec,
+                    rate.tx_packets_per_sec
+                )));
+            }
+            if let Some(usage) = self.usage.get(router, name) {
+                let used = checker::traffic::format_bytes(usage.total());
+                let since = usage.period_start.format("%-d %b");
+                let quota = self
+                    .config
+                    .profile(router)
+                    .and_then(|profile| profile.plan.quotas.get(name));
+                column = column.push(widget::text::caption(match quota {
+                    Some(&quota) => format!(
+                        "Used {} of {} since {} · {} left",
+                        used,
+                        checker::traffic::format_bytes(quota),
+                        since,
+                        checker::traffic::format_bytes(quota.saturating_sub(usage.total()))
+                    ),
+                    None => format!("Used {} since {}", used, since),
+                }));
+            }
+            let gateway = status.ipv6_routes().find(|route| route.is_default());
+            if let Some(route) = gateway {
+                column = column.push(widget::text::caption(format!(
+                    "IPv6 gateway {}",
+                    route.nexthop
+                )));
+            }
+
+            // Only upstream interfaces have a default route, a private LAN address is normal
+            if status.is_cgnat() {
+                column = column.push(widget::text::caption(
+                    "⚠ Behind carrier-grade NAT, incoming connections won't reach the router",
+                ));
+            } else if status.is_private_ip() && status.default_gateway().is_some() {
+                column = column.push(widget::text::caption(
+                    "⚠ Private address, another NAT sits in front of the router",
+                ));
+            }
+        }
+        if let Some(history) = state
+            .history
+            .get(name)
+            .filter(|history| !history.is_empty())
+        {
+            column = column.push(self.history_graph(history));
+        }
+        for stats in state.sla.get(name).into_iter().flatten() {
+            column = column.push(widget::text::caption(sla_text(stats)));
+        }
+        match restart {
+            Some(Restart::Running) | None => {}
+            Some(Restart::Done(took)) => {
+                column = column.push(widget::text::caption(format!(
+                    "Reconnected {} after the restart",
+                    checker::status::format_duration(took.as_secs())
+                )));
+            }
+            Some(Restart::Failed(error)) => {
+                column = column.push(error_text(error));
+            }
+        }
+
+        widget::row()
+            .spacing(10)
+            .push(widget::text(indicator))
+            .push(column)
+            .push(restart_button)
+            .into()
+    }
+
+    /// Download and upload sparklines of one interface over the selected window, with
+    /// periods the interface was mostly down marked by `×`.
+    fn history_graph<'a>(&self, history: &History) -> Element<'a, Message> {
+        let window = self.history_window;
+        let buckets = history.buckets(window.duration(), GRAPH_COLUMNS, Instant::now());
+        let peak =
+            |rate: fn(&Bucket) -> f64| buckets.iter().flatten().map(rate).fold(0.0, f64::max);
+        let rx_peak = peak(|bucket| bucket.rx_bytes_per_sec);
+        let tx_peak = peak(|bucket| bucket.tx_bytes_per_sec);
+
+        let line = |rate: fn(&Bucket) -> f64, peak: f64| -> String {
+            buckets
+                .iter()
+                .map(|bucket| match bucket {
+                    None => ' ',
+                    Some(bucket) if bucket.up_ratio < 0.5 => '×',
+                    Some(bucket) => sparkline_char(rate(bucket), peak),
+                })
+                .collect()
+        };
+
+        widget::column()
+            .push(
+                widget::text::caption(format!(
+                    "↓ {}",
+                    line(|bucket| bucket.rx_bytes_per_sec, rx_peak)
+                ))
+                .font(cosmic::font::mono()),
+            )
+            .push(
+                widget::text::caption(format!(
+                    "↑ {}",
+                    line(|bucket| bucket.tx_bytes_per_sec, tx_peak)
+                ))
+                .font(cosmic::font::mono()),
+            )
+            .push(widget::text::caption(format!(
+                "Peak ↓ {} ↑ {} in the last {}",
+                checker::traffic::format_rate(rx_peak),
+                checker::traffic::format_rate(tx_peak),
+                window.label()
+            )))
+            .into()
+    }
+
+    /// Host key prompts and interface rows of one router.
+    fn router_section<'a>(&'a self, profile: &'a RouterProfile) -> Element<'a, Message> {
+        let state = self.state(&profile.name);
+
+        let edit_button = button(widget::text("Edit"))
+            .on_press(Message::EditRouter(Some(profile.name.clone())))
+            .padding(8);
+
+        let mut section = widget::list_column().padding(5).spacing(10).add(
+            widget::row()
+                .spacing(10)
+                .push(widget::container(widget::text::heading(&profile.name)).width(Length::Fill))
+                .push(edit_button),
+        );
+
+        // Commands stay blocked until the router's host key is trusted
+        if profile.router.host.is_empty() {
+            section = section.add(widget::text::caption(
+                "No host configured, looking for the router at the default gateway",
+            ));
+        } else if let Some(key) = &state.pending_host_key {
+            let trust_button = button(widget::text("Trust"))
+                .on_press(Message::TrustHostKey(profile.name.clone()))
+                .padding(8);
+            section = section
+                .add(widget::settings::item(
+                    "New host key",
+                    widget::text(key.fingerprint.clone()),
+                ))
+                .add(trust_button);
+        } else if state.host_key_mismatch {
+            let trusted = profile
+                .router
+                .host_key
+                .as_ref()
+                .map(|key| key.fingerprint.clone())
+                .unwrap_or_default();
+            let forget_button = button(widget::text("Forget Key"))
+                .on_press(Message::ForgetHostKey(profile.name.clone()))
+                .padding(8);
+            section = section
+                .add(widget::text(format!(
+                    "The router's host key no longer matches the trusted key {}",
+                    trusted
+                )))
+                .add(forget_button);
+        } else if let Some(error) = &state.error {
+            if let Some(label) = state.failure_label() {
+                section = section.add(widget::text::heading(format!(
+                    "{} after {} failed refreshes",
+                    label, state.failures
+                )));
+            }
+            if let Some(since) = state.stale_since() {
+                section = section.add(widget::text::caption(format!(
+                    "Stale since {}, showing the last known status",
+                    since
+                )));
+            }
+            section = section.add(error_text(error));
+        }
+
+        for name in &profile.router.interfaces {
+            section = section.add(self.interface_row(&profile.name, name));
+        }
+
+        section.into()
+    }
+
+    /// Interface details and actions shown when the popup opens.
+    fn view_status(&self) -> Element<'_, Message> {
+        let refresh_button = button(widget::text("Refresh"))
+            .on_press(Message::Refresh)
+            .padding(8);
+
+        let add_button = button(widget::text("Add router"))
+            .on_press(Message::EditRouter(None))
+            .padding(8);
+
+        // Place the buttons in a row
+        let button_row = widget::row()
+            .spacing(10) // space between buttons
+            .push(refresh_button)
+            .push(add_button);
+
+        // Window of the traffic graphs, the selected one is shown disabled
+        let mut windows = widget::row()
+            .spacing(10)
+            .push(widget::text("Traffic history"));
+        for window in HistoryWindow::ALL {
+            windows = windows.push(
+                button(widget::text(window.label()))
+                    .on_press_maybe(
+                        (window != self.history_window).then_some(Message::ShowHistory(window)),
+                    )
+                    .padding(4),
+            );
+        }
+
+        let mut content = widget::column().spacing(10).push(windows);
+        for profile in &self.config.routers {
+            content = content.push(self.router_section(profile));
+        }
+
+        let show_rates = widget::settings::item(
+            "Show speed in panel",
+            widget::toggler(self.config.show_rates_in_panel).on_toggle(Message::ShowRatesInPanel),
+        );
+
+        let mut notify = widget::column()
+            .spacing(4)
+            .push(widget::text::heading("Notify when"));
+        for kind in ChangeKind::ALL {
+            notify = notify.push(widget::settings::item(
+                kind.label(),
+                widget::toggler(self.config.notifications.enabled(kind))
+                    .on_toggle(move |show| Message::ShowNotifications(kind, show)),
+            ));
+        }
+
+        let template_error = self.panel_template.parse::<PanelTemplate>().err();
+        let template_input = widget::text_input(panel::DEFAULT_TEMPLATE, &self.panel_template)
+            .on_input(Message::PanelTemplate);
+        let mut template = widget::column().spacing(4).push(template_input);
+        template = template.push(widget::text::caption(match template_error {
+            Some(why) => why.to_string(),
+            None => format!(
+                "Placeholders: {}",
+                Placeholder::ALL
+                    .iter()
+                    .map(|placeholder| format!("{{{}}}", placeholder.name()))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }));
+        let panel_text = widget::settings::item("Panel text", template);
+        let icon_only = widget::settings::item(
+            "Only show an icon in the panel",
+            widget::toggler(self.config.panel_icon_only).on_toggle(Message::PanelIconOnly),
+        );
+
+        content
+            .push(self.journal_list())
+            .push(panel_text)
+            .push(icon_only)
+            .push(show_rates)
+            .push(notify)
+            .push(button_row)
+            .into()
+    }
+
+    /// Recent journal entries, newest first, with the outage each "came up" ended.
+    fn journal_list(&self) -> Element<'_, Message> {
+        let mut column = widget::column()
+            .spacing(4)
+            .push(widget::text::heading("Events"));
+
+        if self.journal.entries().is_empty() {
+            column = column.push(widget::text::caption("Nothing happened yet"));
+        }
+
+        let mut list = widget::column().spacing(2);
+        for (entry, outage) in self.journal.with_outages().rev().take(JOURNAL_ROWS) {
+            let mut line = format!(
+                "{} · {}/{} · {}",
+                entry.at.format("%-d %b %H:%M"),
+                entry.router,
+                entry.interface,
+                entry.event.label()
+            );
+            if let Some(outage) = outage {
+                line.push_str(&format!(
+                    " after {} down",
+                    checker::status::format_duration(outage.num_seconds().max(0) as u64)
+                ));
+            }
+            if !entry.detail.is_empty() {
+                line.push_str(&format!(" · {}", entry.detail));
+            }
+            list = list.push(widget::text::caption(line));
+        }
+        column =
+            column.push(widget::container(widget::scrollable(list)).max_height(JOURNAL_HEIGHT));
+
+        if let Some(path) = self.journal.path() {
+            column = column.push(widget::text::caption(format!("Kept in {}", path.display())));
+        }
+        column.into()
+    }
+
+    /// Form for editing the connection settings of one router.
+    fn view_settings(&self) -> Element<'_, Message> {
+        let errors = &self.settings_errors;
+
+        let test_result = match (&self.connection_test, self.testing_connection) {
+            (_, true) => widget::text("Testing connection…").into(),
+            (Some(Ok(summary)), false) => widget::text(summary.clone()).into(),
+            (Some(Err(why)), false) => error_text(why),
+            (None, false) => widget::text("").into(),
+        };
+
+        let back_button = button(widget::text("Back"))
+            .on_press(Message::ShowPage(PopupPage::Status))
+            .padding(8);
+
+        let test_button = button(widget::text("Test connection"))
+            .on_press_maybe(
+                (errors.is_empty() && !self.testing_connection).then_some(Message::TestConnection),
+            )
+            .padding(8);
+
+        let save_button = button(widget::text("Save"))
+            .on_press_maybe(errors.is_empty().then_some(Message::SaveSettings))
+            .padding(8);
+
+        let mut button_row = widget::row()
+            .spacing(10)
+            .push(back_button)
+            .push(test_button)
+            .push(save_button);
+
+        // The last router can't be removed, there would be nothing left to show
+        if self.editing.is_some() {
+            let remove_button = button(widget::text("Remove"))
+                .on_press_maybe((self.config.routers.len() > 1).then_some(Message::RemoveRouter))
+                .padding(8);
+            button_row = button_row.push(remove_button);
+        }
+
+        let form = widget::list_column()
+            .padding(5)
+            .spacing(10)
+            .add(settings_input(
+                "Name",
+                &self.settings.name,
+                SettingsField::Name,
+                errors.name,
+            ))
+            .add(settings_input(
+                "Host",
+                &self.settings.host,
+                SettingsField::Host,
+                errors.host,
+            ))
+            .add(settings_input(
+                "Port",
+                &self.settings.port,
+                SettingsField::Port,
+                errors.port,
+            ))
+            .add(settings_input(
+                "Username",
+                &self.settings.username,
+                SettingsField::Username,
+                errors.username,
+            ))
+            .add(settings_input(
+                "Interfaces",
+                &self.settings.interfaces,
+                SettingsField::Interfaces,
+                errors.interfaces,
+            ))
+            .add(widget::settings::item(
+                "Connect via",
+                widget::dropdown(
+                    &TRANSPORT_LABELS,
+                    Transport::ALL
+                        .iter()
+                        .position(|transport| *transport == self.settings.transport),
+                    |index| Message::SettingsTransport(Transport::ALL[index]),
+                ),
+            ));
+
+        let form = if self.settings.transport.uses_ssh() {
+            form.add(settings_input(
+                "Key path",
+                &self.settings.private_key_path,
+                SettingsField::PrivateKeyPath,
+                errors.private_key_path,
+            ))
+        } else {
+            let password = widget::secure_input("Password", &self.settings.password, None, true)
+                .on_input(|value| Message::SettingsInput(SettingsField::Password, value));
+            form.add(widget::settings::item(
+                "Scheme",
+                widget::dropdown(
+                    &SCHEME_LABELS,
+                    HttpScheme::ALL
+                        .iter()
+                        .position(|scheme| *scheme == self.settings.scheme),
+                    |index| Message::SettingsScheme(HttpScheme::ALL[index]),
+                ),
+            ))
+            .add(widget::settings::item("Password", password))
+        };
+
+        let form = form
+            .add(settings_input(
+                "Billing day",
+                &self.settings.billing_day,
+                SettingsField::BillingDay,
+                errors.billing_day,
+            ))
+            .add(settings_input(
+                "Quotas",
+                &self.settings.quotas,
+                SettingsField::Quotas,
+                errors.quotas,
+            ))
+            .add(settings_input(
+                "Alert at (%)",
+                &self.settings.quota_alerts,
+                SettingsField::QuotaAlerts,
+                errors.quota_alerts,
+            ));
+
+        form.add(test_result).add(button_row).into()
+    }
+}
+
+/// Keeps the password of the router profile `name` in the keyring, removing it when empty.
+fn store_password(name: String, password: String) -> Task<cosmic::Action<Message>> {
+    Task::perform(secrets::store_password(name, password), |result| {
+        if let Err(why) = result {
+            eprintln!("Error storing router password: {}", why);
+        }
+        cosmic::Action::None
+    })
+}
+
+/// One line of availability figures, e.g. `7d: 99.95% up · 2 outages, longest 3m 1s`.
+fn sla_text(stats: &SlaStats) -> String {
+    let secs = |delta: chrono::TimeDelta| delta.num_seconds().max(0) as u64;
+    let mut text = format!("{}: {:.2}% up", stats.window.label(), stats.uptime_percent);
+    match (stats.longest_outage, stats.mean_time_between_failures) {
+        (Some(longest), Some(mtbf)) => text.push_str(&format!(
+            " · {} outage{}, longest {} · MTBF {}",
+            stats.outages,
+            if stats.outages == 1 { "" } else { "s" },
+            checker::status::format_duration(secs(longest)),
+            checker::status::format_duration(secs(mtbf))
+        )),
+        _ => text.push_str(" · no outages"),
+    }
+    text
+}
+
+/// Block character showing `value` relative to `peak`.
+fn sparkline_char(value: f64, peak: f64) -> char {
+    const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    if peak <= 0.0 {
+        return LEVELS[0];
+    }
+    let level = (value / peak * (LEVELS.len() - 1) as f64).round() as usize;
+    LEVELS[level.min(LEVELS.len() - 1)]
+}
+
+/// An error with the suggested fix underneath.
+fn error_text<'a>(error: &AppError) -> Element<'a, Message> {
+    let mut column = widget::column()
+        .spacing(4)
+        .push(widget::text(error.to_string()));
+    if let Some(suggestion) = error.suggestion() {
+        column = column.push(widget::text::caption(suggestion));
+    }
+    column.into()
+}
+
+/// Names of [`Transport::ALL`] in the settings dropdown.
+const TRANSPORT_LABELS: [&str; 3] = ["System ssh", "Built-in SSH client", "HTTP (rpcd)"];
+
+/// Names of [`HttpScheme::ALL`] in the settings dropdown.
+const SCHEME_LABELS: [&str; 3] = ["HTTP", "HTTPS", "HTTPS, self-signed certificate"];
+
+/// A labelled text input with its validation message underneath.
+fn settings_input<'a>(
+    label: &'static str,
+    value: &'a str,
+    field: SettingsField,
+    error: Option<&'static str>,
+) -> Element<'a, Message> {
+    let input = widget::text_input(label, value)
+        .on_input(move |value| Message::SettingsInput(field, value));
+
+    let mut column = widget::column().spacing(4).push(input);
+    if let Some(error) = error {
+        column = column.push(widget::text::caption(error));
+    }
+
+    widget::settings::item(label, column).into()
+}
@@ -0,0 +1,188 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+use super::http;
+use super::ssh::host_literal;
+use super::status::{OpenWrtConfig, Transport};
+
+/// Kernel routing tables the default gateway is read from
+const IPV4_ROUTES: &str = "/proc/net/route";
+const IPV6_ROUTES: &str = "/proc/net/ipv6_route";
+
+/// Route flags from `linux/route.h`
+const RTF_UP: u32 = 0x1;
+const RTF_GATEWAY: u32 = 0x2;
+
+/// How long each probe of the gateway may take
+const PROBE_TIMEOUT_SECS: u64 = 3;
+
+/// Default gateway with the lowest metric in the contents of `/proc/net/route`
+fn ipv4_gateway(table: &str) -> Option<Ipv4Addr> {
+    // Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let hex = |index: usize| u32::from_str_radix(fields.get(index)?, 16).ok();
+            let (destination, gateway, flags) = (hex(1)?, hex(2)?, hex(3)?);
+            let (metric, mask) = (fields.get(6)?.parse::<u32>().ok()?, hex(7)?);
+
+            let default = destination == 0 && mask == 0;
+            let usable = flags & (RTF_UP | RTF_GATEWAY) == RTF_UP | RTF_GATEWAY;
+            // Addresses are printed as the raw value in network byte order
+            (default && usable && gateway != 0)
+                .then(|| (metric, Ipv4Addr::from(gateway.to_ne_bytes())))
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, gateway)| gateway)
+}
+
+/// Default gateway with the lowest metric in the contents of `/proc/net/ipv6_route`,
+/// along with the interface it is reached through
+fn ipv6_gateway(table: &str) -> Option<(Ipv6Addr, String)> {
+    // dest dest_len src src_len next_hop metric refcnt use flags iface
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return None;
+            }
+            let hex = |index: usize| u32::from_str_radix(fields[index], 16).ok();
+            let (destination, next_hop) = (ipv6(fields[0])?, ipv6(fields[4])?);
+            let (prefix_len, metric, flags) = (hex(1)?, hex(5)?, hex(8)?);
+
+            let default = destination.is_unspecified() && prefix_len == 0;
+            let usable = flags & (RTF_UP | RTF_GATEWAY) == RTF_UP | RTF_GATEWAY;
+            (default && usable && !next_hop.is_unspecified())
+                .then(|| (metric, next_hop, fields[9].to_string()))
+        })
+        .min_by_key(|(metric, _, _)| *metric)
+        .map(|(_, gateway, iface)| (gateway, iface))
+}
+
+/// 32 hex digits as printed in `/proc/net/ipv6_route`
+fn ipv6(hex: &str) -> Option<Ipv6Addr> {
+    u128::from_str_radix(hex, 16).ok().map(Ipv6Addr::from)
+}
+
+/// The local default gateway as a host string, preferring IPv4. Link-local IPv6
+/// gateways carry the interface as zone, e.g. `fe80::1%eth0`.
+pub async fn default_gateway() -> Option<String> {
+    if let Ok(table) = tokio::fs::read_to_string(IPV4_ROUTES).await {
+        if let Some(gateway) = ipv4_gateway(&table) {
+            return Some(gateway.to_string());
+        }
+    }
+
+    let table = tokio::fs::read_to_string(IPV6_ROUTES).await.ok()?;
+    let (gateway, iface) = ipv6_gateway(&table)?;
+    Some(ipv6_host(gateway, &iface))
+}
+
+/// `gateway` as a host string, with `iface` as zone when it is link-local
+fn ipv6_host(gateway: Ipv6Addr, iface: &str) -> String {
+    if gateway.is_unicast_link_local() {
+        format!("{}%{}", gateway, iface)
+    } else {
+        gateway.to_string()
+    }
+}
+
+/// Whether an SSH server greets on `host:port`
+async fn probe_ssh(host: &str, port: u16) -> bool {
+    let timeout = Duration::from_secs(PROBE_TIMEOUT_SECS);
+
+    let greeting = async {
+        let mut stream = tokio::net::TcpStream::connect((host_literal(host), port))
+            .await
+            .ok()?;
+        let mut banner = [0; 4];
+        stream.read_exact(&mut banner).await.ok()?;
+        Some(banner)
+    };
+    matches!(tokio::time::timeout(timeout, greeting).await, Ok(Some(banner)) if &banner == b"SSH-")
+}
+
+/// Propose the local default gateway as the router, connecting over SSH when it runs
+/// an SSH server and over rpcd when only that answers. `None` when there is no default
+/// route or the gateway offers neither.
+pub async fn discover_router() -> Option<OpenWrtConfig> {
+    let host = default_gateway().await?;
+    let ssh_port = Transport::SshCommand.default_port();
+    let http_port = Transport::HttpUbus.default_port();
+    let timeout = Duration::from_secs(PROBE_TIMEOUT_SECS);
+
+    let (ssh, rpcd) = tokio::join!(
+        probe_ssh(&host, ssh_port),
+        http::probe(&host, http_port, timeout)
+    );
+    let transport = match (ssh, rpcd) {
+        (true, _) => Transport::SshCommand,
+        (false, true) => Transport::HttpUbus,
+        (false, false) => return None,
+    };
+
+    Some(OpenWrtConfig {
+        host,
+        port: transport.default_port(),
+        transport,
+        ..OpenWrtConfig::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTE: &str = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+wwan0\t00000000\t00000000\t0001\t0\t0\t0\t00000000\t0\t0\t0
+eth0\t00000000\tFE01A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+";
+
+    const IPV6_ROUTE: &str = "\
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe800000000000000000000000000001 00000400 00000001 00000000 00450003    wlan0
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 00000000 00000001 00000000 00000001    wwan0
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe80000000000000020000fffe000001 00000064 00000001 00000000 00450003     eth0
+fd000000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001     eth0
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 ffffffff 00000001 00000000 00200200       lo
+";
+
+    #[test]
+    fn ipv4_gateway_with_lowest_metric() {
+        // The wwan0 default route has the lowest metric but no gateway
+        assert_eq!(ipv4_gateway(ROUTE), Some(Ipv4Addr::new(192, 168, 1, 254)));
+    }
+
+    #[test]
+    fn ipv4_without_gateway_route() {
+        // Leaves the device route of wwan0 and the eth0 subnet
+        let table = ROUTE
+            .lines()
+            .filter(|line| !line.contains("\t0003\t"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(ipv4_gateway(&table), None);
+    }
+
+    #[test]
+    fn ipv6_gateway_with_lowest_metric() {
+        let (gateway, iface) = ipv6_gateway(IPV6_ROUTE).unwrap();
+        assert_eq!(gateway, "fe80::200:ff:fe00:1".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(iface, "eth0");
+    }
+
+    #[test]
+    fn link_local_gateway_carries_zone() {
+        let (gateway, iface) = ipv6_gateway(IPV6_ROUTE).unwrap();
+        assert_eq!(ipv6_host(gateway, &iface), "fe80::200:ff:fe00:1%eth0");
+
+        let global = "2001:db8::1".parse().unwrap();
+        assert_eq!(ipv6_host(global, "eth0"), "2001:db8::1");
+    }
+}
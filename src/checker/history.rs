@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long samples are kept, the longest window the popup offers
+pub const RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// State and transfer rates of an interface at one point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub at: Instant,
+    pub up: bool,
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+}
+
+/// Averages over the samples that fell into one slice of a window
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bucket {
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    /// Share of the samples taken while the interface was up, from 0 to 1
+    pub up_ratio: f64,
+}
+
+/// Ring buffer of the samples of one interface, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    samples: VecDeque<Sample>,
+}
+
+impl History {
+    /// Append a sample and drop the ones older than [`RETENTION`]
+    pub fn push(&mut self, sample: Sample) {
+        while self
+            .samples
+            .front()
+            .is_some_and(|oldest| sample.at.duration_since(oldest.at) > RETENTION)
+        {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Samples taken during the `window` before `now`
+    pub fn since(&self, window: Duration, now: Instant) -> impl Iterator<Item = &Sample> {
+        self.samples
+            .iter()
+            .filter(move |sample| now.saturating_duration_since(sample.at) <= window)
+    }
+
+    /// Split the `window` before `now` into `count` equal slices and average each of them,
+    /// oldest first. Slices without samples are `None`.
+    pub fn buckets(&self, window: Duration, count: usize, now: Instant) -> Vec<Option<Bucket>> {
+        let mut sums = vec![(Bucket::default(), 0usize); count];
+        let slice = window.as_secs_f64() / count as f64;
+
+        for sample in self.since(window, now) {
+            let age = now.saturating_duration_since(sample.at).as_secs_f64();
+            let index = count - 1 - ((age / slice) as usize).min(count - 1);
+            let (sum, samples) = &mut sums[index];
+            sum.rx_bytes_per_sec += sample.rx_bytes_per_sec;
+            sum.tx_bytes_per_sec += sample.tx_bytes_per_sec;
+            sum.up_ratio += if sample.up { 1.0 } else { 0.0 };
+            *samples += 1;
+        }
+
+        sums.into_iter()
+            .map(|(sum, samples)| {
+                let samples = samples as f64;
+                (samples > 0.0).then(|| Bucket {
+                    rx_bytes_per_sec: sum.rx_bytes_per_sec / samples,
+                    tx_bytes_per_sec: sum.tx_bytes_per_sec / samples,
+                    up_ratio: sum.up_ratio / samples,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(at: Instant, up: bool, rx: f64) -> Sample {
+        Sample {
+            at,
+            up,
+            rx_bytes_per_sec: rx,
+            tx_bytes_per_sec: rx / 10.0,
+        }
+    }
+
+    #[test]
+    fn push_keeps_samples_up_to_the_retention() {
+        let start = Instant::now();
+        let mut history = History::default();
+        history.push(sample(start, true, 0.0));
+        history.push(sample(start + Duration::from_secs(3600), true, 0.0));
+        // Exactly as old as the retention still counts
+        history.push(sample(start + RETENTION, true, 0.0));
+        assert_eq!(history.samples.len(), 3);
+
+        history.push(sample(
+            start + RETENTION + Duration::from_secs(1),
+            true,
+            0.0,
+        ));
+        let kept: Vec<_> = history.samples.iter().map(|sample| sample.at).collect();
+        assert_eq!(
+            kept,
+            [
+                start + Duration::from_secs(3600),
+                start + RETENTION,
+                start + RETENTION + Duration::from_secs(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn buckets_average_the_samples_of_each_slice() {
+        let start = Instant::now();
+        let now = start + Duration::from_secs(120);
+        let ago = |secs| now - Duration::from_secs(secs);
+
+        let mut history = History::default();
+        for sample in [
+            // Older than the window
+            sample(ago(61), true, 1000.0),
+            // Exactly at the window's start, counted in the oldest slice
+            sample(ago(60), true, 50.0),
+            sample(ago(50), false, 70.0),
+            // On the edge between two slices, counted in the older one
+            sample(ago(10), true, 400.0),
+            sample(ago(5), true, 100.0),
+            sample(ago(0), false, 300.0),
+        ] {
+            history.push(sample);
+        }
+
+        let buckets = history.buckets(Duration::from_secs(60), 6, now);
+        assert_eq!(buckets.len(), 6);
+        assert_eq!(
+            buckets[0],
+            Some(Bucket {
+                rx_bytes_per_sec: 60.0,
+                tx_bytes_per_sec: 6.0,
+                up_ratio: 0.5,
+            })
+        );
+        assert_eq!(buckets[1..4], [None, None, None]);
+        assert_eq!(
+            buckets[4],
+            Some(Bucket {
+                rx_bytes_per_sec: 400.0,
+                tx_bytes_per_sec: 40.0,
+                up_ratio: 1.0,
+            })
+        );
+        assert_eq!(
+            buckets[5],
+            Some(Bucket {
+                rx_bytes_per_sec: 200.0,
+                tx_bytes_per_sec: 20.0,
+                up_ratio: 0.5,
+            })
+        );
+    }
+
+    #[test]
+    fn buckets_of_an_empty_history() {
+        let buckets = History::default().buckets(Duration::from_secs(60), 3, Instant::now());
+        assert_eq!(buckets, [None, None, None]);
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::AsyncWriteExt;
+
+use super::native;
+use super::status::{xdg_dir, AppError, OpenWrtConfig, Transport};
+
+/// Alias `ssh` files the pinned key under, so the known hosts entry doesn't depend on the port
+pub const HOST_KEY_ALIAS: &str = "openwrt-interface-status";
+
+/// Numbers the temporary files of `write_known_hosts` within this process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Key types to ask the router for, most preferred first
+const KEY_TYPES: [&str; 3] = ["ssh-ed25519", "ecdsa-sha2-nistp256", "ssh-rsa"];
+
+/// Public host key of the router, pinned on first connect
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HostKey {
+    /// Key in `authorized_keys` format, e.g. `ssh-ed25519 AAAA...`
+    pub key: String,
+    /// SHA256 fingerprint as printed by `ssh-keygen -l`
+    pub fingerprint: String,
+}
+
+/// Ask the router for its host key without authenticating
+pub async fn scan_host_key(config: &OpenWrtConfig) -> Result<HostKey, AppError> {
+    match config.transport {
+        Transport::SshCommand => keyscan(config).await,
+        Transport::NativeSsh => native::scan_host_key(config).await,
+        Transport::HttpUbus => Err(AppError::Other(String::from(
+            "The HTTP transport has no SSH host key",
+        ))),
+    }
+}
+
+/// Read the host key with `ssh-keyscan` and fingerprint it with `ssh-keygen`
+async fn keyscan(config: &OpenWrtConfig) -> Result<HostKey, AppError> {
+    let output = tokio::process::Command::new("ssh-keyscan")
+        .args(["-T", "10", "-p", &config.port.to_string(), "-t"])
+        .arg("ed25519,ecdsa,rsa")
+        .arg(super::ssh::host_literal(&config.host))
+        .output()
+        .await?;
+
+    // Each line reads `<host> <type> <base64>`, comments go to stderr
+    let stdout = String::from_utf8(output.stdout)?;
+    let key = KEY_TYPES
+        .iter()
+        .find_map(|wanted| {
+            stdout.lines().find_map(|line| {
+                let mut fields = line.split_whitespace().skip(1);
+                match (fields.next(), fields.next()) {
+                    (Some(kind), Some(blob)) if kind == *wanted => {
+                        Some(format!("{} {}", kind, blob))
+                    }
+                    _ => None,
+                }
+            })
+        })
+        .ok_or_else(|| {
+            AppError::HostUnreachable(format!("No SSH host key received from {}", config.host))
+        })?;
+
+    let fingerprint = fingerprint(&key).await?;
+    Ok(HostKey { key, fingerprint })
+}
+
+/// Compute the SHA256 fingerprint of a public key with `ssh-keygen`
+async fn fingerprint(key: &str) -> Result<String, AppError> {
+    let mut child = tokio::process::Command::new("ssh-keygen")
+        .args(["-l", "-E", "sha256", "-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(key.as_bytes()).await?;
+        stdin.write_all(b"\n").await?;
+    }
+
+    // Output reads `<bits> SHA256:<hash> <comment> (<type>)`
+    let output = child.wait_with_output().await?;
+    String::from_utf8(output.stdout)?
+        .split_whitespace()
+        .nth(1)
+        .filter(|fingerprint| fingerprint.starts_with("SHA256:"))
+        .map(str::to_string)
+        .ok_or_else(|| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            AppError::Other(format!("Could not fingerprint host key: {}", stderr))
+        })
+}
+
+/// Write a known hosts file containing only the pinned key and return its path.
+///
+/// The file lives in a directory only the user can access, under `$XDG_RUNTIME_DIR` or
+/// else the state directory, and is only rewritten when the pinned key changed.
+pub async fn write_known_hosts(config: &OpenWrtConfig, key: &HostKey) -> Result<PathBuf, AppError> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| xdg_dir("XDG_STATE_HOME", ".local/state"))
+        .ok_or_else(|| {
+            AppError::Other(String::from(
+                "No private directory for the known hosts file, set XDG_RUNTIME_DIR or HOME",
+            ))
+        })?;
+    let dir = base.join(HOST_KEY_ALIAS);
+    tokio::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .await?;
+    // An existing directory may have been created with laxer permissions
+    tokio::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).await?;
+
+    let host: String = config
+        .host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = dir.join(format!("{}-{}.known_hosts", host, config.port));
+    let contents = format!("{} {}\n", HOST_KEY_ALIAS, key.key);
+    if tokio::fs::read_to_string(&path).await.ok().as_deref() == Some(contents.as_str()) {
+        return Ok(path);
+    }
+
+    // Replace the file in one step so `ssh` never reads a half-written key. Each writer
+    // gets its own temporary file, as several connections may pin the same key at once.
+    let temp = path.with_extension(format!(
+        "known_hosts.{}-{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp)
+        .await?;
+    let written = async {
+        file.write_all(contents.as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&temp, &path).await
+    }
+    .await;
+    if written.is_err() {
+        let _ = tokio::fs::remove_file(&temp).await;
+    }
+    written?;
+    Ok(path)
+}
@@ -0,0 +1,576 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use super::ssh::{host_literal, CONNECT_TIMEOUT_SECS};
+use super::status::{AppError, HttpScheme, OpenWrtConfig};
+use super::transport::{ubus_status_error, RouterTransport};
+
+/// Session id rpcd accepts for `session.login` before any session exists
+const NULL_SESSION: &str = "00000000000000000000000000000000";
+/// JSON-RPC error rpcd answers with for unknown or expired sessions
+const ACCESS_DENIED: i64 = -32002;
+/// Seconds before the announced expiry at which a session is renewed
+const EXPIRY_MARGIN_SECS: u64 = 15;
+
+/// Whole-request timeout, generous enough for an interface restart on a slow router
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Host name requests to zoned link-local addresses are sent to, URLs can't carry zones
+const LINK_LOCAL_HOST: &str = "link-local.invalid";
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    client_builder()
+        .build()
+        .expect("HTTP client with static settings")
+});
+
+static UNVERIFIED_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    client_builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .expect("HTTP client with static settings")
+});
+
+fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+}
+
+/// Logged in rpcd session
+#[derive(Debug, Clone)]
+struct Session {
+    token: String,
+    /// Lifetime rpcd extends the session to on every call
+    timeout: Duration,
+    expires_at: Instant,
+}
+
+/// Sessions by endpoint and user, shared by every transport talking to the same router
+static SESSIONS: LazyLock<Mutex<HashMap<(String, String), Session>>> =
+    LazyLock::new(Default::default);
+
+/// Outcome of one JSON-RPC `call`
+enum Reply {
+    /// ubus returned status 0, with the method's data if it sent any
+    Ok(Value),
+    /// ubus returned a non-zero status code
+    Status(i64),
+    /// rpcd rejected the session
+    AccessDenied,
+}
+
+/// Issues ubus calls as JSON-RPC requests to rpcd behind uhttpd's `/ubus` endpoint
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    config: OpenWrtConfig,
+    endpoint: Endpoint,
+}
+
+impl HttpTransport {
+    pub fn new(config: OpenWrtConfig) -> Self {
+        let endpoint = Endpoint::new(&config.host, config.port, config.scheme);
+        Self { config, endpoint }
+    }
+
+    fn session_key(&self) -> (String, String) {
+        (self.endpoint.url.clone(), self.config.username.clone())
+    }
+
+    /// A session token that has not expired yet, logging in again when needed
+    async fn session(&self) -> Result<String, AppError> {
+        let cached = SESSIONS.lock().unwrap().get(&self.session_key()).cloned();
+        if let Some(session) = cached.filter(|session| session.expires_at > Instant::now()) {
+            return Ok(session.token);
+        }
+
+        self.login().await
+    }
+
+    async fn login(&self) -> Result<String, AppError> {
+        let credentials = json!({
+            "username": self.config.username,
+            "password": self.config.password,
+        });
+        let reply = self
+            .rpc(NULL_SESSION, "session", "login", credentials)
+            .await?;
+
+        let data = match reply {
+            Reply::Ok(data) => data,
+            Reply::Status(_) | Reply::AccessDenied => {
+                return Err(AppError::AuthFailed(format!(
+                    "rpcd login as {} failed",
+                    self.config.username
+                )))
+            }
+        };
+
+        let token = data["ubus_rpc_session"]
+            .as_str()
+            .ok_or_else(|| malformed("login reply without ubus_rpc_session"))?
+            .to_string();
+        let timeout = Duration::from_secs(data["expires"].as_u64().unwrap_or(300));
+
+        SESSIONS.lock().unwrap().insert(
+            self.session_key(),
+            Session {
+                token: token.clone(),
+                timeout,
+                expires_at: Instant::now() + expiry_window(timeout),
+            },
+        );
+
+        Ok(token)
+    }
+
+    /// Push the cached expiry forward after rpcd accepted the session again
+    fn touch(&self, token: &str) {
+        if let Some(session) = SESSIONS.lock().unwrap().get_mut(&self.session_key()) {
+            if session.token == token {
+                session.expires_at = Instant::now() + expiry_window(session.timeout);
+            }
+        }
+    }
+
+    fn forget(&self) {
+        SESSIONS.lock().unwrap().remove(&self.session_key());
+    }
+
+    async fn rpc(
+        &self,
+        session: &str,
+        object: &str,
+        method: &str,
+        args: Value,
+    ) -> Result<Reply, AppError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "call",
+            "params": [session, object, method, args],
+        });
+
+        let (client, url) = self.endpoint.connection()?;
+        let response: Value = client
+            .post(url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error") {
+            return match error["code"].as_i64() {
+                Some(ACCESS_DENIED) => Ok(Reply::AccessDenied),
+                _ => Err(malformed(&format!("rpcd error: {}", error))),
+            };
+        }
+
+        // `result` is `[status]` or `[status, data]`
+        let result = response["result"]
+            .as_array()
+            .ok_or_else(|| malformed("reply without result"))?;
+        match result.first().and_then(Value::as_i64) {
+            Some(0) => Ok(Reply::Ok(result.get(1).cloned().unwrap_or(Value::Null))),
+            Some(code) => Ok(Reply::Status(code)),
+            None => Err(malformed("reply without status code")),
+        }
+    }
+}
+
+impl RouterTransport for HttpTransport {
+    async fn ubus_call(&self, object: &str, method: &str, args: Value) -> Result<Value, AppError> {
+        let mut token = self.session().await?;
+
+        for attempt in 0..2 {
+            match self.rpc(&token, object, method, args.clone()).await? {
+                Reply::Ok(data) => {
+                    self.touch(&token);
+                    return Ok(data);
+                }
+                // The router restarted or expired the session early, log in once more
+                Reply::AccessDenied if attempt == 0 => {
+                    self.forget();
+                    token = self.login().await?;
+                }
+                Reply::AccessDenied => break,
+                Reply::Status(code) => return Err(ubus_status_error(object, method, code)),
+            }
+        }
+
+        Err(AppError::PermissionDenied(format!(
+            "rpcd denied access to {} {}",
+            object, method
+        )))
+    }
+}
+
+/// The `/ubus` endpoint of a router
+#[derive(Debug, Clone)]
+struct Endpoint {
+    /// URL of the endpoint, with the zone of a link-local address encoded as `%25`
+    url: String,
+    scheme: HttpScheme,
+    /// Link-local address with zone, which `reqwest` can't parse from a URL
+    zoned: Option<(Ipv6Addr, String, u16)>,
+}
+
+impl Endpoint {
+    fn new(host: &str, port: u16, scheme: HttpScheme) -> Self {
+        let host = host_literal(host);
+        let zoned = host.split_once('%').and_then(|(address, zone)| {
+            let address = address.parse::<Ipv6Addr>().ok()?;
+            Some((address, zone.to_string(), port))
+        });
+        let url = if host.contains(':') {
+            format!(
+                "{}://[{}]:{}/ubus",
+                scheme.as_str(),
+                host.replace('%', "%25"),
+                port
+            )
+        } else {
+            format!("{}://{}:{}/ubus", scheme.as_str(), host, port)
+        };
+        Self { url, scheme, zoned }
+    }
+
+    /// Client and URL to send requests with. Zoned addresses get a client of their own
+    /// that resolves a placeholder host name to the scoped socket address.
+    fn connection(&self) -> Result<(reqwest::Client, String), AppError> {
+        let shared = match self.scheme {
+            HttpScheme::Http | HttpScheme::Https => &CLIENT,
+            HttpScheme::HttpsUnverified => &UNVERIFIED_CLIENT,
+        };
+        let Some((address, zone, port)) = &self.zoned else {
+            return Ok(((*shared).clone(), self.url.clone()));
+        };
+
+        let scope_id = interface_index(zone)?;
+        let target = SocketAddr::V6(SocketAddrV6::new(*address, *port, 0, scope_id));
+        let client = client_builder()
+            .danger_accept_invalid_certs(self.scheme == HttpScheme::HttpsUnverified)
+            .resolve(LINK_LOCAL_HOST, target)
+            .build()?;
+        let url = format!(
+            "{}://{}:{}/ubus",
+            self.scheme.as_str(),
+            LINK_LOCAL_HOST,
+            port
+        );
+        Ok((client, url))
+    }
+}
+
+/// Index of the network interface a zone names, zones may also be the index itself
+fn interface_index(zone: &str) -> Result<u32, AppError> {
+    if let Ok(index) = zone.parse() {
+        return Ok(index);
+    }
+    std::fs::read_to_string(format!("/sys/class/net/{}/ifindex", zone))
+        .ok()
+        .and_then(|index| index.trim().parse().ok())
+        .ok_or_else(|| AppError::HostUnreachable(format!("No network interface {}", zone)))
+}
+
+/// Whether rpcd answers JSON-RPC on `host`, without logging in
+pub(super) async fn probe(host: &str, port: u16, timeout: Duration) -> bool {
+    // Any JSON-RPC reply will do, an access denied error is the usual one
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "list",
+        "params": [NULL_SESSION, "session"],
+    });
+    let Ok((client, url)) = Endpoint::new(host, port, HttpScheme::Http).connection() else {
+        return false;
+    };
+    let response = client
+        .post(url)
+        .timeout(timeout)
+        .json(&request)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) => response
+            .json::<Value>()
+            .await
+            .is_ok_and(|reply| reply.get("jsonrpc").is_some()),
+        Err(_) => false,
+    }
+}
+
+fn expiry_window(timeout: Duration) -> Duration {
+    timeout.saturating_sub(Duration::from_secs(EXPIRY_MARGIN_SECS))
+}
+
+fn malformed(message: &str) -> AppError {
+    AppError::MalformedResponse(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::checker::status::Transport;
+
+    const PASSWORD: &str = "secret";
+
+    /// Stand-in for rpcd answering the calls the transport makes
+    #[derive(Default)]
+    struct Rpcd {
+        sessions: Mutex<HashSet<String>>,
+        logins: AtomicU32,
+        /// Reject every session right after handing it out, like a router that keeps
+        /// restarting rpcd
+        forgetful: AtomicBool,
+    }
+
+    impl Rpcd {
+        fn reply(&self, request: &Value) -> Value {
+            let params = &request["params"];
+            let session = params[0].as_str().unwrap_or_default();
+            let call = (
+                request["method"].as_str().unwrap_or_default(),
+                params[1].as_str().unwrap_or_default(),
+                params[2].as_str().unwrap_or_default(),
+            );
+
+            let result = match call {
+                ("call", "session", "login") if params[3]["password"] == PASSWORD => {
+                    let login = self.logins.fetch_add(1, Ordering::SeqCst) + 1;
+                    let token = format!("{:032x}", login);
+                    if !self.forgetful.load(Ordering::SeqCst) {
+                        self.sessions.lock().unwrap().insert(token.clone());
+                    }
+                    json!([0, { "ubus_rpc_session": token, "expires": 300 }])
+                }
+                ("call", "session", "login") => json!([6]),
+                _ if !self.sessions.lock().unwrap().contains(session) => {
+                    return json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "error": { "code": ACCESS_DENIED, "message": "Access denied" },
+                    });
+                }
+                ("call", "network.interface.wan", "status") => {
+                    json!([0, { "up": true, "uptime": 3600, "l3_device": "eth1" }])
+                }
+                ("call", "network.interface.guest", _) => json!([6]),
+                _ => json!([4]),
+            };
+            json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+        }
+    }
+
+    /// Serve `rpcd` on a free local port and return the port
+    async fn serve(rpcd: Arc<Rpcd>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(stream, rpcd.clone()));
+            }
+        });
+        port
+    }
+
+    /// Answer the JSON-RPC requests of one keep-alive connection
+    async fn handle(stream: TcpStream, rpcd: Arc<Rpcd>) {
+        let mut stream = BufReader::new(stream);
+        loop {
+            let mut length = 0;
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                    return;
+                }
+                let header = line.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap_or(0);
+                    }
+                }
+            }
+
+            let mut body = vec![0; length];
+            if stream.read_exact(&mut body).await.is_err() {
+                return;
+            }
+            let reply = serde_json::from_slice(&body)
+                .map(|request| rpcd.reply(&request))
+                .unwrap_or(Value::Null)
+                .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                reply.len(),
+                reply
+            );
+            if stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+
+    fn transport(port: u16, password: &str) -> HttpTransport {
+        HttpTransport::new(OpenWrtConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: "root".to_string(),
+            transport: Transport::HttpUbus,
+            password: password.to_string(),
+            ..OpenWrtConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn session_is_reused_between_calls() {
+        let rpcd = Arc::new(Rpcd::default());
+        let transport = transport(serve(rpcd.clone()).await, PASSWORD);
+
+        for _ in 0..2 {
+            let status = transport
+                .ubus_call("network.interface.wan", "status", json!({}))
+                .await
+                .unwrap();
+            assert_eq!(status["l3_device"], "eth1");
+        }
+        assert_eq!(rpcd.logins.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn logs_in_again_after_access_denied() {
+        let rpcd = Arc::new(Rpcd::default());
+        let transport = transport(serve(rpcd.clone()).await, PASSWORD);
+
+        transport
+            .ubus_call("network.interface.wan", "status", json!({}))
+            .await
+            .unwrap();
+        // rpcd restarted and lost its sessions
+        rpcd.sessions.lock().unwrap().clear();
+        transport
+            .ubus_call("network.interface.wan", "status", json!({}))
+            .await
+            .unwrap();
+
+        assert_eq!(rpcd.logins.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_fresh_sessions_are_denied() {
+        let rpcd = Arc::new(Rpcd::default());
+        rpcd.forgetful.store(true, Ordering::SeqCst);
+        let transport = transport(serve(rpcd.clone()).await, PASSWORD);
+
+        let result = transport
+            .ubus_call("network.interface.wan", "status", json!({}))
+            .await;
+
+        assert!(matches!(result, Err(AppError::PermissionDenied(_))));
+        assert_eq!(rpcd.logins.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn wrong_password_fails_login() {
+        let rpcd = Arc::new(Rpcd::default());
+        let transport = transport(serve(rpcd.clone()).await, "wrong");
+
+        let result = transport
+            .ubus_call("network.interface.wan", "status", json!({}))
+            .await;
+
+        assert!(matches!(result, Err(AppError::AuthFailed(_))));
+    }
+
+    #[tokio::test]
+    async fn ubus_status_codes_are_mapped() {
+        let rpcd = Arc::new(Rpcd::default());
+        let transport = transport(serve(rpcd).await, PASSWORD);
+
+        let missing = transport
+            .ubus_call("network.interface.lte", "status", json!({}))
+            .await;
+        assert!(matches!(missing, Err(AppError::InterfaceNotFound(name)) if name == "lte"));
+
+        let denied = transport
+            .ubus_call("network.interface.guest", "status", json!({}))
+            .await;
+        assert!(matches!(denied, Err(AppError::PermissionDenied(_))));
+    }
+
+    #[tokio::test]
+    async fn probe_recognises_rpcd() {
+        let port = serve(Arc::new(Rpcd::default())).await;
+        assert!(probe("127.0.0.1", port, Duration::from_secs(2)).await);
+
+        // A port nothing listens on any more
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        assert!(!probe("127.0.0.1", closed_port, Duration::from_secs(2)).await);
+    }
+
+    #[test]
+    fn endpoint_url_follows_scheme() {
+        let url = |host, port, scheme| Endpoint::new(host, port, scheme).url;
+
+        assert_eq!(
+            url("192.168.1.1", 80, HttpScheme::Http),
+            "http://192.168.1.1:80/ubus"
+        );
+        assert_eq!(
+            url("router.lan", 443, HttpScheme::Https),
+            "https://router.lan:443/ubus"
+        );
+        assert_eq!(
+            url("[fd00::1]", 8443, HttpScheme::HttpsUnverified),
+            "https://[fd00::1]:8443/ubus"
+        );
+    }
+
+    #[test]
+    fn zone_is_encoded_in_url() {
+        let endpoint = Endpoint::new("fe80::1%eth0", 80, HttpScheme::Http);
+        assert_eq!(endpoint.url, "http://[fe80::1%25eth0]:80/ubus");
+
+        let bracketed = Endpoint::new("[fe80::1%eth0]", 80, HttpScheme::Http);
+        assert_eq!(bracketed.url, endpoint.url);
+    }
+
+    #[test]
+    fn zoned_requests_go_to_placeholder_host() {
+        let endpoint = Endpoint::new("fe80::1%2", 443, HttpScheme::HttpsUnverified);
+        let (_, url) = endpoint.connection().unwrap();
+        assert_eq!(url, format!("https://{}:443/ubus", LINK_LOCAL_HOST));
+    }
+
+    #[test]
+    fn unknown_zone_is_unreachable() {
+        let endpoint = Endpoint::new("fe80::1%no-such-iface", 80, HttpScheme::Http);
+        assert!(matches!(
+            endpoint.connection(),
+            Err(AppError::HostUnreachable(_))
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::status::xdg_dir;
+
+/// Days of entries kept, enough for the longest SLA window
+const RETENTION_DAYS: i64 = 30;
+
+/// What happened to an interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalEvent {
+    WentDown,
+    CameUp,
+    AddressChanged,
+    /// The uptime started over while the interface stayed up
+    Reconnected,
+    ProtoChanged,
+    /// The user restarted the interface from the popup
+    Restarted,
+}
+
+impl JournalEvent {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::WentDown => "Went down",
+            Self::CameUp => "Came up",
+            Self::AddressChanged => "Address changed",
+            Self::Reconnected => "Reconnected",
+            Self::ProtoChanged => "Protocol changed",
+            Self::Restarted => "Restarted by user",
+        }
+    }
+}
+
+/// One line of the journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub at: DateTime<Local>,
+    pub router: String,
+    pub interface: String,
+    pub event: JournalEvent,
+    /// What exactly changed, e.g. the new address
+    #[serde(default)]
+    pub detail: String,
+}
+
+/// Interface events in the order they happened, appended to a JSON Lines file so the
+/// record outlives the applet
+#[derive(Debug, Clone, Default)]
+pub struct Journal {
+    path: Option<PathBuf>,
+    entries: Vec<JournalEntry>,
+    /// How long the interface had been down, for each entry that ends an outage
+    outages: Vec<Option<TimeDelta>>,
+    /// When each interface that is down went down, by router and interface name
+    down_since: BTreeMap<(String, String), DateTime<Local>>,
+    /// When entries past the retention were last dropped
+    pruned_at: Option<DateTime<Local>>,
+}
+
+impl Journal {
+    /// Read the entries written so far to `path`. Lines that don't parse are skipped so
+    /// one damaged line doesn't cost the whole record.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut entries = Vec::new();
+        match path.as_deref().map(std::fs::read_to_string) {
+            Some(Ok(lines)) => {
+                for line in lines.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str(line) {
+                        Ok(entry) => entries.push(entry),
+                        Err(why) => eprintln!("Skipping journal line {:?}: {}", line, why),
+                    }
+                }
+            }
+            Some(Err(why)) if why.kind() != io::ErrorKind::NotFound => {
+                eprintln!("Error reading the event journal: {}", why);
+            }
+            _ => {}
+        }
+
+        let mut journal = Self::new(path, entries);
+        if journal.prune(Local::now()) {
+            if let Err(why) = journal.rewrite() {
+                eprintln!("Error pruning the event journal: {}", why);
+            }
+        }
+        journal
+    }
+
+    fn new(path: Option<PathBuf>, entries: Vec<JournalEntry>) -> Self {
+        let mut journal = Self {
+            path,
+            ..Default::default()
+        };
+        for entry in entries {
+            journal.push(entry);
+        }
+        journal
+    }
+
+    /// Append `entry` in memory and measure the outage it ends
+    fn push(&mut self, entry: JournalEntry) {
+        let key = (entry.router.clone(), entry.interface.clone());
+        let outage = match entry.event {
+            JournalEvent::WentDown => {
+                self.down_since.entry(key).or_insert(entry.at);
+                None
+            }
+            JournalEvent::CameUp => self.down_since.remove(&key).map(|since| entry.at - since),
+            _ => None,
+        };
+        self.entries.push(entry);
+        self.outages.push(outage);
+    }
+
+    /// Drop the entries older than the retention, except the last `went down` or `came up`
+    /// of each interface before it that the SLA figures start from. Returns whether any
+    /// entry was dropped.
+    fn prune(&mut self, now: DateTime<Local>) -> bool {
+        self.pruned_at = Some(now);
+        let cutoff = now - TimeDelta::days(RETENTION_DAYS);
+        let old = self
+            .entries
+            .iter()
+            .take_while(|entry| entry.at < cutoff)
+            .count();
+
+        let mut last_changes = BTreeMap::new();
+        for (index, entry) in self.entries[..old].iter().enumerate() {
+            if matches!(entry.event, JournalEvent::WentDown | JournalEvent::CameUp) {
+                last_changes.insert((&entry.router, &entry.interface), index);
+            }
+        }
+        let kept: BTreeSet<usize> = last_changes.into_values().collect();
+        if kept.len() == old {
+            return false;
+        }
+
+        let entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| *index >= old || kept.contains(index))
+            .map(|(_, entry)| entry)
+            .collect();
+        *self = Self {
+            pruned_at: self.pruned_at,
+            ..Self::new(self.path.take(), entries)
+        };
+        true
+    }
+
+    /// Replace the file with the entries kept in memory
+    fn rewrite(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let temp = path.with_extension("jsonl.tmp");
+        let mut file = std::fs::File::create(&temp)?;
+        for entry in &self.entries {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp, path)
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Add `entry` to the journal and its file. The entry is kept in memory even when
+    /// writing the file fails.
+    pub fn record(&mut self, entry: JournalEntry) -> io::Result<()> {
+        let line = serde_json::to_string(&entry)?;
+        let at = entry.at;
+        self.push(entry);
+
+        // Dropping old entries rewrites the whole file, so only do it about once a day
+        let due = self
+            .pruned_at
+            .is_none_or(|pruned_at| at - pruned_at >= TimeDelta::days(1));
+        if due && self.prune(at) {
+            return self.rewrite();
+        }
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Whether `interface` on `router` was up according to its last `went down` or
+    /// `came up` entry, and when that entry was made
+    pub fn last_state(&self, router: &str, interface: &str) -> Option<(bool, DateTime<Local>)> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.router == router && entry.interface == interface)
+            .find_map(|entry| match entry.event {
+                JournalEvent::WentDown => Some((false, entry.at)),
+                JournalEvent::CameUp => Some((true, entry.at)),
+                _ => None,
+            })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Every entry along with how long the interface had been down for the entries that
+    /// end an outage, oldest first
+    pub fn with_outages(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (&JournalEntry, Option<TimeDelta>)> {
+        self.entries.iter().zip(self.outages.iter().copied())
+    }
+}
+
+/// `$XDG_STATE_HOME/<app_id>/journal.jsonl`, falling back to `~/.local/state`
+pub fn journal_path(app_id: &str) -> Option<PathBuf> {
+    Some(
+        xdg_dir("XDG_STATE_HOME", ".local/state")?
+            .join(app_id)
+            .join("journal.jsonl"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(minutes: i64, interface: &str, event: JournalEvent) -> JournalEntry {
+        JournalEntry {
+            at: DateTime::from_timestamp(1_700_000_000 + minutes * 60, 0)
+                .unwrap()
+                .into(),
+            router: String::from("Router"),
+            interface: interface.to_string(),
+            event,
+            detail: String::new(),
+        }
+    }
+
+    fn journal(entries: Vec<JournalEntry>) -> Journal {
+        Journal::new(None, entries)
+    }
+
+    #[test]
+    fn last_state_skips_other_events_and_interfaces() {
+        let journal = journal(vec![
+            entry(0, "wan", JournalEvent::WentDown),
+            entry(5, "wan", JournalEvent::CameUp),
+            entry(6, "wan", JournalEvent::AddressChanged),
+            entry(7, "lte", JournalEvent::WentDown),
+        ]);
+
+        assert_eq!(
+            journal.last_state("Router", "wan"),
+            Some((true, entry(5, "wan", JournalEvent::CameUp).at))
+        );
+        assert_eq!(
+            journal.last_state("Router", "lte").map(|(up, _)| up),
+            Some(false)
+        );
+        assert_eq!(journal.last_state("Router", "guest"), None);
+        assert_eq!(journal.last_state("Other", "wan"), None);
+    }
+
+    #[test]
+    fn outages_are_measured_from_the_first_went_down() {
+        let journal = journal(vec![
+            entry(0, "wan", JournalEvent::WentDown),
+            entry(2, "wan", JournalEvent::WentDown),
+            entry(5, "wan", JournalEvent::CameUp),
+            entry(9, "wan", JournalEvent::CameUp),
+        ]);
+
+        let outages: Vec<_> = journal.with_outages().map(|(_, outage)| outage).collect();
+        assert_eq!(outages, [None, None, Some(TimeDelta::minutes(5)), None]);
+    }
+
+    #[test]
+    fn prune_keeps_the_last_state_change_before_the_retention() {
+        let day = 24 * 60;
+        let mut journal = journal(vec![
+            entry(0, "wan", JournalEvent::WentDown),
+            entry(5, "wan", JournalEvent::CameUp),
+            entry(6, "wan", JournalEvent::AddressChanged),
+            entry(7, "lte", JournalEvent::WentDown),
+            entry(20 * day, "wan", JournalEvent::WentDown),
+            entry(35 * day, "lte", JournalEvent::CameUp),
+        ]);
+        let now = entry(40 * day, "wan", JournalEvent::CameUp).at;
+
+        assert!(journal.prune(now));
+        let kept: Vec<_> = journal
+            .with_outages()
+            .map(|(entry, outage)| (entry.interface.as_str(), entry.event, outage))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("wan", JournalEvent::CameUp, None),
+                ("lte", JournalEvent::WentDown, None),
+                ("wan", JournalEvent::WentDown, None),
+                (
+                    "lte",
+                    JournalEvent::CameUp,
+                    Some(TimeDelta::minutes(35 * day - 7))
+                ),
+            ]
+        );
+        assert_eq!(
+            journal.last_state("Router", "wan").map(|(up, _)| up),
+            Some(false)
+        );
+
+        // Nothing left to drop
+        assert!(!journal.prune(now));
+    }
+
+    #[test]
+    fn record_measures_outages_as_they_end() {
+        let mut journal = journal(vec![entry(0, "wan", JournalEvent::WentDown)]);
+        journal
+            .record(entry(3, "wan", JournalEvent::CameUp))
+            .unwrap();
+
+        let last = journal.with_outages().next_back().map(|(_, outage)| outage);
+        assert_eq!(last, Some(Some(TimeDelta::minutes(3))));
+    }
+}
@@ -1 +1,12 @@
-pub mod status;
\ No newline at end of file
+pub mod discovery;
+pub mod history;
+pub mod host_key;
+mod http;
+pub mod journal;
+mod native;
+pub mod sla;
+mod ssh;
+pub mod status;
+pub mod traffic;
+pub mod transport;
+pub mod usage;
@@ -0,0 +1,350 @@
+use futures_util::stream;
+use russh::client::{self, Handle};
+use russh::keys::{HashAlg, PrivateKeyWithHashAlg, PublicKey};
+use russh::ChannelMsg;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use super::host_key::HostKey;
+use super::ssh::{
+    host_literal, parse_ubus_reply, CONNECT_TIMEOUT_SECS, KEEPALIVE_COUNT_MAX,
+    KEEPALIVE_INTERVAL_SECS,
+};
+use super::status::{expand_home, AppError, OpenWrtConfig};
+use super::transport::{
+    command_error, listen_error, ubus_command, ubus_listen_command, EventStream, RouterTransport,
+};
+
+/// Keys tried in order when no private key path is configured, like `ssh` does
+const DEFAULT_KEYS: [&str; 3] = ["~/.ssh/id_ed25519", "~/.ssh/id_ecdsa", "~/.ssh/id_rsa"];
+
+/// Identifies a login on a router, sessions are shared between configs with the same key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SessionKey {
+    host: String,
+    port: u16,
+    username: String,
+    private_key_path: Option<String>,
+    host_key: Option<String>,
+}
+
+impl From<&OpenWrtConfig> for SessionKey {
+    fn from(config: &OpenWrtConfig) -> Self {
+        Self {
+            host: config.host.clone(),
+            port: config.port,
+            username: config.username.clone(),
+            private_key_path: config.private_key_path.clone(),
+            host_key: config.host_key.as_ref().map(|key| key.key.clone()),
+        }
+    }
+}
+
+/// Authenticated connection to a router, reopened on demand after it drops
+type SharedSession = Arc<Mutex<Option<Arc<Handle<Client>>>>>;
+
+/// Open sessions by login, kept while a router is configured with that login
+static SESSIONS: LazyLock<StdMutex<HashMap<SessionKey, SharedSession>>> =
+    LazyLock::new(Default::default);
+
+/// Client side of the SSH connection, only accepts the pinned host key
+struct Client {
+    /// Key the server must present, in `authorized_keys` format
+    expected: Option<String>,
+    /// Where the presented key is stored for [`scan_host_key`]
+    presented: Option<Arc<StdMutex<Option<PublicKey>>>>,
+}
+
+impl client::Handler for Client {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &PublicKey,
+    ) -> Result<bool, Self::Error> {
+        if let Some(presented) = &self.presented {
+            *presented.lock().unwrap() = Some(server_public_key.clone());
+        }
+
+        let Some(expected) = &self.expected else {
+            return Ok(false);
+        };
+        Ok(openssh_key(server_public_key).as_deref() == Some(expected.as_str()))
+    }
+}
+
+/// Render a public key as `<type> <base64>` without comment, the format pinned in config
+fn openssh_key(key: &PublicKey) -> Option<String> {
+    let line = key.to_openssh().ok()?;
+    let mut fields = line.split_whitespace();
+    Some(format!("{} {}", fields.next()?, fields.next()?))
+}
+
+fn client_config() -> Arc<client::Config> {
+    Arc::new(client::Config {
+        keepalive_interval: Some(Duration::from_secs(KEEPALIVE_INTERVAL_SECS)),
+        keepalive_max: KEEPALIVE_COUNT_MAX as usize,
+        ..Default::default()
+    })
+}
+
+async fn connect(config: &OpenWrtConfig, handler: Client) -> Result<Handle<Client>, AppError> {
+    let address = (host_literal(&config.host), config.port);
+    let connecting = client::connect(client_config(), address, handler);
+
+    match tokio::time::timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS), connecting).await {
+        Ok(Ok(handle)) => Ok(handle),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Err(AppError::Timeout(format!(
+            "Connection to {} timed out",
+            config.host
+        ))),
+    }
+}
+
+/// Connect to the router and log in with the configured or a default private key
+async fn open_session(config: &OpenWrtConfig) -> Result<Handle<Client>, AppError> {
+    let key = config.host_key.as_ref().ok_or(AppError::HostKeyUnknown)?;
+    let handler = Client {
+        expected: Some(key.key.clone()),
+        presented: None,
+    };
+    let mut handle = connect(config, handler).await?;
+
+    let candidates: Vec<PathBuf> = match &config.private_key_path {
+        Some(path) => vec![expand_home(path)],
+        None => DEFAULT_KEYS.iter().map(|path| expand_home(path)).collect(),
+    };
+
+    let mut unloadable = None;
+    let mut tried = false;
+    for path in candidates.iter().filter(|path| path.is_file()) {
+        // A default key may need a passphrase, the next one may still work
+        let private_key = match russh::keys::load_secret_key(path, None) {
+            Ok(private_key) => private_key,
+            Err(e) => {
+                let why = format!("Could not load {}: {}", path.display(), e);
+                unloadable = Some(AppError::Other(why));
+                continue;
+            }
+        };
+        tried = true;
+        let hash_alg = handle.best_supported_rsa_hash().await?.flatten();
+        let auth = handle
+            .authenticate_publickey(
+                &config.username,
+                PrivateKeyWithHashAlg::new(Arc::new(private_key), hash_alg),
+            )
+            .await?;
+
+        if auth.success() {
+            return Ok(handle);
+        }
+    }
+
+    if let Some(why) = unloadable.filter(|_| !tried) {
+        return Err(why);
+    }
+    Err(AppError::AuthFailed(format!(
+        "Public key authentication as {} failed",
+        config.username
+    )))
+}
+
+/// What a command run over an exec channel printed, and how it exited
+struct ExecOutput {
+    exit_status: Option<u32>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// Run `command` over a fresh exec channel and collect its output
+async fn exec(handle: &Handle<Client>, command: &str) -> Result<ExecOutput, AppError> {
+    let mut channel = handle.channel_open_session().await?;
+    channel.exec(true, command).await?;
+
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    let mut exit_status = None;
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { data } => stdout.extend_from_slice(&data),
+            ChannelMsg::ExtendedData { data, ext: 1 } => stderr.extend_from_slice(&data),
+            ChannelMsg::ExitStatus { exit_status: code } => exit_status = Some(code),
+            _ => {}
+        }
+    }
+
+    Ok(ExecOutput {
+        exit_status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Runs ubus calls as exec channels of one long-lived session per router login
+#[derive(Debug, Clone)]
+pub struct NativeSshTransport {
+    config: OpenWrtConfig,
+}
+
+impl NativeSshTransport {
+    pub fn new(config: OpenWrtConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl RouterTransport for NativeSshTransport {
+    async fn ubus_call(
+        &self,
+        object: &str,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, AppError> {
+        let command = ubus_command(object, method, &args);
+        let output = execute_command(&self.config, &command).await?;
+
+        if output.exit_status != Some(0) {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(command_error(object, method, output.exit_status, &stderr));
+        }
+        parse_ubus_reply(&output.stdout)
+    }
+
+    async fn listen(&self, path: &str) -> Result<EventStream, AppError> {
+        let handle = session_handle(&shared_session(&self.config), &self.config).await?;
+        let mut channel = handle.channel_open_session().await?;
+        channel.exec(true, ubus_listen_command(path)).await?;
+
+        // Opening the channel says nothing about the remote command, wait for its first line
+        let mut buffer = Vec::new();
+        while !buffer.contains(&b'\n') {
+            match channel.wait().await {
+                Some(ChannelMsg::Data { data }) => buffer.extend_from_slice(&data),
+                Some(ChannelMsg::ExitStatus { .. } | ChannelMsg::Eof) => return Err(listen_error()),
+                Some(ChannelMsg::Close) | None => {
+                    return Err(AppError::Disconnected(String::from(
+                        "The connection closed before listening started",
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+        let end = buffer.iter().position(|byte| *byte == b'\n').unwrap_or(0);
+        buffer.drain(..=end);
+
+        // Output arrives in arbitrary chunks, `ubus listen` prints one JSON object per line
+        let state = (handle, channel, buffer);
+        let events = stream::unfold(state, |(handle, mut channel, mut buffer)| async move {
+            loop {
+                if let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    if let Ok(event) = serde_json::from_slice(&line) {
+                        return Some((event, (handle, channel, buffer)));
+                    }
+                    continue;
+                }
+
+                match channel.wait().await {
+                    Some(ChannelMsg::Data { data }) => buffer.extend_from_slice(&data),
+                    Some(ChannelMsg::Eof | ChannelMsg::Close | ChannelMsg::ExitStatus { .. })
+                    | None => return None,
+                    Some(_) => {}
+                }
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
+}
+
+/// The session slot shared by every transport logging in the same way as `config`
+fn shared_session(config: &OpenWrtConfig) -> SharedSession {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .entry(SessionKey::from(config))
+        .or_default()
+        .clone()
+}
+
+/// Close the sessions of logins no longer used by any of `configs`
+pub fn retain_sessions<'a>(configs: impl IntoIterator<Item = &'a OpenWrtConfig>) {
+    let used: HashSet<SessionKey> = configs.into_iter().map(SessionKey::from).collect();
+    SESSIONS.lock().unwrap().retain(|key, _| used.contains(key));
+}
+
+/// The open session in `shared`, logging in first if there is none or it was closed
+async fn session_handle(
+    shared: &SharedSession,
+    config: &OpenWrtConfig,
+) -> Result<Arc<Handle<Client>>, AppError> {
+    let mut session = shared.lock().await;
+    let open = session
+        .as_ref()
+        .filter(|handle| !handle.is_closed())
+        .cloned();
+
+    match open {
+        Some(handle) => Ok(handle),
+        None => {
+            let handle = Arc::new(open_session(config).await?);
+            *session = Some(handle.clone());
+            Ok(handle)
+        }
+    }
+}
+
+/// Execute a command on the router over the shared session, reconnecting once if it dropped
+async fn execute_command(config: &OpenWrtConfig, command: &str) -> Result<ExecOutput, AppError> {
+    let shared = shared_session(config);
+
+    for attempt in 0..2 {
+        let handle = session_handle(&shared, config).await?;
+
+        match exec(&handle, command).await {
+            // The session went away between ticks, drop it and log in again
+            Err(e)
+                if attempt == 0
+                    && (matches!(e, AppError::Disconnected(_)) || handle.is_closed()) =>
+            {
+                shared.lock().await.take();
+            }
+            result => return result,
+        }
+    }
+
+    unreachable!("the second attempt always returns")
+}
+
+/// Ask the router for its host key without authenticating
+pub async fn scan_host_key(config: &OpenWrtConfig) -> Result<HostKey, AppError> {
+    let presented = Arc::new(StdMutex::new(None));
+    let handler = Client {
+        expected: None,
+        presented: Some(presented.clone()),
+    };
+
+    // The handler rejects every key, so the connection itself always fails
+    let result = connect(config, handler).await;
+
+    let key = presented
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or_else(|| match result {
+            // Without a key the handshake never got that far, report why
+            Err(AppError::HostKeyMismatch) | Ok(_) => {
+                AppError::HostUnreachable(format!("No SSH host key received from {}", config.host))
+            }
+            Err(e) => e,
+        })?;
+
+    Ok(HostKey {
+        key: openssh_key(&key).unwrap_or_default(),
+        fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+    })
+}
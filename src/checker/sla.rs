@@ -0,0 +1,236 @@
+use chrono::{DateTime, Local, TimeDelta};
+use serde::Serialize;
+
+use super::journal::{JournalEntry, JournalEvent};
+
+/// Time spans availability is reported for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SlaWindow {
+    Day,
+    Week,
+    Month,
+}
+
+impl SlaWindow {
+    pub const ALL: [SlaWindow; 3] = [Self::Day, Self::Week, Self::Month];
+
+    pub fn duration(self) -> TimeDelta {
+        match self {
+            Self::Day => TimeDelta::days(1),
+            Self::Week => TimeDelta::days(7),
+            Self::Month => TimeDelta::days(30),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Day => "24h",
+            Self::Week => "7d",
+            Self::Month => "30d",
+        }
+    }
+}
+
+/// Availability of one interface over a window ending now
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SlaStats {
+    pub window: SlaWindow,
+    /// Share of the window the interface was up, from 0 to 100
+    pub uptime_percent: f64,
+    /// Outages that overlap the window
+    pub outages: u32,
+    /// Time the interface was up in the window divided by the number of outages, `None`
+    /// without outages
+    #[serde(with = "optional_seconds")]
+    pub mean_time_between_failures: Option<TimeDelta>,
+    /// Longest outage within the window, `None` without outages
+    #[serde(with = "optional_seconds")]
+    pub longest_outage: Option<TimeDelta>,
+}
+
+impl SlaStats {
+    /// Compute the statistics of `interface` on `router` from the `went down` and
+    /// `came up` entries of the journal.
+    ///
+    /// Before its first entry an interface counts as up, unless that entry says it came
+    /// up. Silent reconnects don't count as outages as their length is unknown, and
+    /// neither does a last `went down` entry unless `up_now` says the interface is still
+    /// down.
+    pub fn compute(
+        entries: &[JournalEntry],
+        router: &str,
+        interface: &str,
+        window: SlaWindow,
+        now: DateTime<Local>,
+        up_now: Option<bool>,
+    ) -> Self {
+        let start = now - window.duration();
+        let mut changes = entries
+            .iter()
+            .filter(|entry| entry.router == router && entry.interface == interface)
+            .filter_map(|entry| match entry.event {
+                JournalEvent::WentDown => Some((entry.at, false)),
+                JournalEvent::CameUp => Some((entry.at, true)),
+                _ => None,
+            })
+            .filter(|(at, _)| *at <= now)
+            .peekable();
+
+        // State at the start of the window: that of the last change before it, or the
+        // opposite of the first change when there is none
+        let mut up = changes
+            .peek()
+            .is_none_or(|(at, came_up)| *at < start || !came_up);
+        while let Some((_, came_up)) = changes.next_if(|(at, _)| *at < start) {
+            up = came_up;
+        }
+
+        let mut outages = Vec::new();
+        let mut down_since = (!up).then_some(start);
+        for (at, came_up) in changes {
+            match (down_since, came_up) {
+                (Some(since), true) => {
+                    outages.push(at - since);
+                    down_since = None;
+                }
+                (None, false) => down_since = Some(at),
+                // Repeated entries of the same state change nothing
+                _ => {}
+            }
+        }
+        if let Some(since) = down_since.filter(|_| up_now == Some(false)) {
+            outages.push(now - since);
+        }
+
+        let window_length = window.duration();
+        let downtime = outages
+            .iter()
+            .fold(TimeDelta::zero(), |total, outage| total + *outage);
+        let uptime = (window_length - downtime).max(TimeDelta::zero());
+        let count = outages.len() as u32;
+
+        Self {
+            window,
+            uptime_percent: uptime.num_milliseconds() as f64 * 100.0
+                / window_length.num_milliseconds() as f64,
+            outages: count,
+            mean_time_between_failures: (count > 0).then(|| uptime / count as i32),
+            longest_outage: outages.into_iter().max(),
+        }
+    }
+}
+
+/// Durations as whole seconds, so exported numbers don't depend on chrono's format
+mod optional_seconds {
+    use chrono::TimeDelta;
+    use serde::Serializer;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<TimeDelta>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(delta) => serializer.serialize_some(&delta.num_seconds()),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap().into()
+    }
+
+    /// An entry for `wan` the given number of hours before `now`
+    fn entry(hours_ago: i64, event: JournalEvent) -> JournalEntry {
+        JournalEntry {
+            at: now() - TimeDelta::hours(hours_ago),
+            router: String::from("Router"),
+            interface: String::from("wan"),
+            event,
+            detail: String::new(),
+        }
+    }
+
+    fn day(entries: &[JournalEntry], up_now: Option<bool>) -> SlaStats {
+        SlaStats::compute(entries, "Router", "wan", SlaWindow::Day, now(), up_now)
+    }
+
+    #[test]
+    fn no_entries_is_all_up() {
+        let stats = day(&[], Some(true));
+        assert_eq!(stats.uptime_percent, 100.0);
+        assert_eq!(stats.outages, 0);
+        assert_eq!(stats.longest_outage, None);
+        assert_eq!(stats.mean_time_between_failures, None);
+    }
+
+    #[test]
+    fn state_before_window_carries_over() {
+        // Down since two days ago, up again six hours ago
+        let entries = [
+            entry(48, JournalEvent::WentDown),
+            entry(6, JournalEvent::CameUp),
+        ];
+        let stats = day(&entries, Some(true));
+        assert_eq!(stats.uptime_percent, 25.0);
+        assert_eq!(stats.outages, 1);
+        assert_eq!(stats.longest_outage, Some(TimeDelta::hours(18)));
+    }
+
+    #[test]
+    fn first_came_up_means_down_before() {
+        let stats = day(&[entry(12, JournalEvent::CameUp)], Some(true));
+        assert_eq!(stats.uptime_percent, 50.0);
+        assert_eq!(stats.longest_outage, Some(TimeDelta::hours(12)));
+    }
+
+    #[test]
+    fn trailing_outage_lasts_while_still_down() {
+        let entries = [entry(6, JournalEvent::WentDown)];
+        let stats = day(&entries, Some(false));
+        assert_eq!(stats.uptime_percent, 75.0);
+        assert_eq!(stats.longest_outage, Some(TimeDelta::hours(6)));
+    }
+
+    #[test]
+    fn trailing_outage_of_unknown_length_is_left_out() {
+        let entries = [entry(6, JournalEvent::WentDown)];
+        for up_now in [Some(true), None] {
+            let stats = day(&entries, up_now);
+            assert_eq!(stats.uptime_percent, 100.0);
+            assert_eq!(stats.outages, 0);
+        }
+    }
+
+    #[test]
+    fn repeated_entries_count_once() {
+        let entries = [
+            entry(10, JournalEvent::WentDown),
+            entry(9, JournalEvent::WentDown),
+            entry(8, JournalEvent::CameUp),
+            entry(7, JournalEvent::CameUp),
+            entry(4, JournalEvent::WentDown),
+            entry(3, JournalEvent::CameUp),
+        ];
+        let stats = day(&entries, Some(true));
+        assert_eq!(stats.outages, 2);
+        assert_eq!(stats.longest_outage, Some(TimeDelta::hours(2)));
+        assert_eq!(
+            stats.mean_time_between_failures,
+            Some(TimeDelta::hours(21) / 2)
+        );
+    }
+
+    #[test]
+    fn other_interfaces_and_later_entries_are_ignored() {
+        let mut other = entry(5, JournalEvent::WentDown);
+        other.interface = String::from("lte");
+        let entries = [other, entry(-1, JournalEvent::WentDown)];
+        let stats = day(&entries, Some(true));
+        assert_eq!(stats.outages, 0);
+    }
+}
@@ -0,0 +1,378 @@
+use futures_util::stream;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+use super::host_key;
+use super::status::{AppError, OpenWrtConfig};
+use super::transport::{
+    command_error, listen_error, ubus_command, ubus_listen_command, EventStream, RouterTransport,
+};
+
+/// Seconds to wait for the TCP connection and SSH handshake before giving up
+pub(super) const CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Seconds between keepalive probes on an otherwise idle connection
+pub(super) const KEEPALIVE_INTERVAL_SECS: u64 = 15;
+/// Unanswered keepalive probes after which the connection is considered dead
+pub(super) const KEEPALIVE_COUNT_MAX: u32 = 2;
+
+/// Build the `ssh` argument vector that runs `command` on the router, accepting only the
+/// host key listed in `known_hosts`
+fn ssh_args(config: &OpenWrtConfig, known_hosts: &Path, command: &str) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-o".into(),
+        "StrictHostKeyChecking=yes".into(),
+        "-o".into(),
+        format!("UserKnownHostsFile={}", known_hosts.display()),
+        "-o".into(),
+        "GlobalKnownHostsFile=/dev/null".into(),
+        "-o".into(),
+        format!("HostKeyAlias={}", host_key::HOST_KEY_ALIAS),
+        "-o".into(),
+        format!("ConnectTimeout={}", CONNECT_TIMEOUT_SECS),
+        "-o".into(),
+        format!("ServerAliveInterval={}", KEEPALIVE_INTERVAL_SECS),
+        "-o".into(),
+        format!("ServerAliveCountMax={}", KEEPALIVE_COUNT_MAX),
+        "-p".into(),
+        config.port.to_string(),
+        "-l".into(),
+        config.username.clone(),
+    ];
+
+    // Add identity file if specified
+    if let Some(ref key) = config.private_key_path {
+        args.push("-i".into());
+        args.push(key.clone());
+    }
+
+    // Target and command, after `--` so a host starting with `-` isn't taken for an option
+    args.push("--".into());
+    args.push(host_literal(&config.host).to_string());
+    args.push(command.to_string());
+
+    args
+}
+
+/// Strip the brackets of an `[addr]` IPv6 literal, which `ssh` only accepts bare
+pub(super) fn host_literal(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// Runs every ubus call through a fresh `ssh` process
+#[derive(Debug, Clone)]
+pub struct SshCommandTransport {
+    config: OpenWrtConfig,
+}
+
+impl SshCommandTransport {
+    pub fn new(config: OpenWrtConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl RouterTransport for SshCommandTransport {
+    async fn ubus_call(
+        &self,
+        object: &str,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, AppError> {
+        let command = ubus_command(object, method, &args);
+        let output = execute_ssh_command(&self.config, command).await?;
+
+        if !output.status.success() {
+            let code = output
+                .status
+                .code()
+                .and_then(|code| u32::try_from(code).ok());
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(command_error(object, method, code, &stderr));
+        }
+        parse_ubus_reply(&output.stdout)
+    }
+
+    async fn listen(&self, path: &str) -> Result<EventStream, AppError> {
+        let key = self
+            .config
+            .host_key
+            .as_ref()
+            .ok_or(AppError::HostKeyUnknown)?;
+        let known_hosts = host_key::write_known_hosts(&self.config, key).await?;
+        let command = ubus_listen_command(path);
+
+        // The process lives as long as the stream, dropping it hangs up the connection
+        let mut child = tokio::process::Command::new("ssh")
+            .args(ssh_args(&self.config, &known_hosts, &command))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let mut lines = BufReader::new(stdout).lines();
+
+        // Spawning `ssh` says nothing about the connection, wait for the remote command
+        // to print its first line
+        if !matches!(lines.next_line().await, Ok(Some(_))) {
+            let mut message = Vec::new();
+            stderr.read_to_end(&mut message).await?;
+            let status = child.wait().await?;
+            return Err(match status.code() {
+                Some(SSH_FAILURE) => ssh_error(&String::from_utf8_lossy(&message)),
+                _ => listen_error(),
+            });
+        }
+        // Keep reading what `ssh` prints so it never blocks on a full pipe
+        tokio::spawn(async move {
+            let _ = tokio::io::copy(&mut stderr, &mut tokio::io::sink()).await;
+        });
+
+        // `ubus listen` prints one JSON object per line
+        let events = stream::unfold((child, lines), |(child, mut lines)| async move {
+            loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) => {
+                        if let Ok(event) = serde_json::from_str(&line) {
+                            return Some((event, (child, lines)));
+                        }
+                    }
+                    Ok(None) | Err(_) => return None,
+                }
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
+}
+
+/// Decode what `ubus call` printed, methods without a reply print nothing at all
+pub(super) fn parse_ubus_reply(stdout: &[u8]) -> Result<serde_json::Value, AppError> {
+    if stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(serde_json::Value::Null);
+    }
+    Ok(serde_json::from_slice(stdout)?)
+}
+
+/// Exit code `ssh` uses for its own errors, as opposed to the remote command's
+const SSH_FAILURE: i32 = 255;
+
+/// Execute an SSH command on the OpenWrt router, leaving the remote exit status to the caller
+async fn execute_ssh_command(
+    config: &OpenWrtConfig,
+    command: String,
+) -> Result<std::process::Output, AppError> {
+    let key = config.host_key.as_ref().ok_or(AppError::HostKeyUnknown)?;
+    let known_hosts = host_key::write_known_hosts(config, key).await?;
+
+    let output = tokio::process::Command::new("ssh")
+        .args(ssh_args(config, &known_hosts, &command))
+        .output()
+        .await?;
+
+    if output.status.code() == Some(SSH_FAILURE) {
+        return Err(ssh_error(&String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(output)
+}
+
+/// Classify what `ssh` printed when it failed to run the remote command
+fn ssh_error(stderr: &str) -> AppError {
+    let message = stderr.trim().to_string();
+    let says = |patterns: &[&str]| patterns.iter().any(|pattern| stderr.contains(pattern));
+
+    if says(&["Host key verification failed"]) {
+        AppError::HostKeyMismatch
+    } else if says(&["Permission denied", "Too many authentication failures"]) {
+        AppError::AuthFailed(message)
+    } else if says(&["timed out"]) {
+        AppError::Timeout(message)
+    } else if says(&[
+        "Connection refused",
+        "No route to host",
+        "Network is unreachable",
+        "Could not resolve hostname",
+    ]) {
+        AppError::HostUnreachable(message)
+    } else if says(&["Connection closed", "Connection reset", "Broken pipe"]) {
+        AppError::Disconnected(message)
+    } else {
+        AppError::Other(format!("SSH command failed: {}", message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Options every invocation starts with, up to and including `-p`
+    fn common_options(known_hosts: &str) -> Vec<String> {
+        [
+            "-o",
+            "StrictHostKeyChecking=yes",
+            "-o",
+            &format!("UserKnownHostsFile={}", known_hosts),
+            "-o",
+            "GlobalKnownHostsFile=/dev/null",
+            "-o",
+            &format!("HostKeyAlias={}", host_key::HOST_KEY_ALIAS),
+            "-o",
+            "ConnectTimeout=10",
+            "-o",
+            "ServerAliveInterval=15",
+            "-o",
+            "ServerAliveCountMax=2",
+            "-p",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    fn config(host: &str, port: u16, key: Option<&str>) -> OpenWrtConfig {
+        OpenWrtConfig {
+            host: host.to_string(),
+            port,
+            username: "root".to_string(),
+            private_key_path: key.map(String::from),
+            ..OpenWrtConfig::default()
+        }
+    }
+
+    #[test]
+    fn custom_port_and_key() {
+        let args = ssh_args(
+            &config("192.168.1.1", 2222, Some("~/.ssh/router")),
+            Path::new("/run/user/1000/known_hosts"),
+            "ubus call system board",
+        );
+
+        let mut expected = common_options("/run/user/1000/known_hosts");
+        expected.extend(
+            [
+                "2222",
+                "-l",
+                "root",
+                "-i",
+                "~/.ssh/router",
+                "--",
+                "192.168.1.1",
+                "ubus call system board",
+            ]
+            .map(String::from),
+        );
+        assert_eq!(args, expected);
+    }
+
+    #[test]
+    fn no_key_leaves_out_identity() {
+        let args = ssh_args(
+            &config("router.lan", 22, None),
+            Path::new("/tmp/known_hosts"),
+            "true",
+        );
+
+        let mut expected = common_options("/tmp/known_hosts");
+        expected.extend(["22", "-l", "root", "--", "router.lan", "true"].map(String::from));
+        assert_eq!(args, expected);
+        assert!(!args.iter().any(|arg| arg == "-i"));
+    }
+
+    #[test]
+    fn bracketed_ipv6_is_passed_bare() {
+        let args = ssh_args(
+            &config("[::1]", 22, None),
+            Path::new("/tmp/known_hosts"),
+            "true",
+        );
+
+        let mut expected = common_options("/tmp/known_hosts");
+        expected.extend(["22", "-l", "root", "--", "::1", "true"].map(String::from));
+        assert_eq!(args, expected);
+    }
+
+    #[test]
+    fn host_is_never_an_option() {
+        let args = ssh_args(
+            &config("-oProxyCommand=reboot", 22, None),
+            Path::new("/tmp/known_hosts"),
+            "true",
+        );
+
+        let separator = args.iter().position(|arg| arg == "--").unwrap();
+        assert_eq!(args[separator + 1..], ["-oProxyCommand=reboot", "true"]);
+    }
+
+    #[test]
+    fn host_literal_keeps_other_hosts() {
+        assert_eq!(host_literal("[fe80::1%eth0]"), "fe80::1%eth0");
+        assert_eq!(host_literal("fe80::1"), "fe80::1");
+        assert_eq!(host_literal("openwrt.lan"), "openwrt.lan");
+    }
+
+    #[test]
+    fn ssh_failures_map_to_variants() {
+        let cases: [(&str, fn(String) -> AppError); 12] = [
+            ("Host key verification failed.", |_| {
+                AppError::HostKeyMismatch
+            }),
+            (
+                "root@192.168.1.1: Permission denied (publickey,password).",
+                AppError::AuthFailed,
+            ),
+            (
+                "Received disconnect from 192.168.1.1 port 22:2: Too many authentication failures",
+                AppError::AuthFailed,
+            ),
+            (
+                "ssh: connect to host 192.168.1.1 port 22: Connection timed out",
+                AppError::Timeout,
+            ),
+            (
+                "ssh: connect to host 192.168.1.1 port 22: Connection refused",
+                AppError::HostUnreachable,
+            ),
+            (
+                "ssh: connect to host 10.0.0.1 port 22: No route to host",
+                AppError::HostUnreachable,
+            ),
+            (
+                "ssh: connect to host 10.0.0.1 port 22: Network is unreachable",
+                AppError::HostUnreachable,
+            ),
+            (
+                "ssh: Could not resolve hostname openwrt.lan: Name or service not known",
+                AppError::HostUnreachable,
+            ),
+            (
+                "Connection closed by 192.168.1.1 port 22",
+                AppError::Disconnected,
+            ),
+            (
+                "kex_exchange_identification: read: Connection reset by peer",
+                AppError::Disconnected,
+            ),
+            (
+                "client_loop: send disconnect: Broken pipe",
+                AppError::Disconnected,
+            ),
+            (
+                "Bad owner or permissions on /home/user/.ssh/config",
+                |message| AppError::Other(format!("SSH command failed: {}", message)),
+            ),
+        ];
+
+        for (stderr, expected) in cases {
+            assert_eq!(
+                ssh_error(&format!("{}\r\n", stderr)),
+                expected(stderr.to_string()),
+                "{}",
+                stderr
+            );
+        }
+    }
+}
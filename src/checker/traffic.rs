@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::status::AppError;
+use super::transport::RouterTransport;
+
+/// Fastest rate a 32 bit counter is believed to wrap at. Only old drivers keep such
+/// counters, on links well below 1 Gbit/s.
+const MAX_WRAP_BYTES_PER_SEC: f64 = 125e6;
+
+/// Traffic counters of a network device since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceCounters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+/// Counters of a device and when they were read
+#[derive(Debug, Clone, Copy)]
+pub struct CounterSample {
+    pub counters: DeviceCounters,
+    pub at: Instant,
+}
+
+/// Average transfer rates between two samples
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    pub rx_bytes_per_sec: f64,
+    pub tx_bytes_per_sec: f64,
+    pub rx_packets_per_sec: f64,
+    pub tx_packets_per_sec: f64,
+}
+
+/// Read the counters of every network device with one `network.device status` call
+pub async fn fetch_device_counters<T: RouterTransport>(
+    transport: &T,
+) -> Result<BTreeMap<String, DeviceCounters>, AppError> {
+    let reply = transport
+        .ubus_call("network.device", "status", json!({}))
+        .await?;
+
+    let mut counters = BTreeMap::new();
+    if let Some(devices) = reply.as_object() {
+        for (name, device) in devices {
+            if let Some(statistics) = device.get("statistics") {
+                counters.insert(name.clone(), DeviceCounters::deserialize(statistics)?);
+            }
+        }
+    }
+
+    Ok(counters)
+}
+
+/// Rates between `previous` and `current`, `None` when the counters were reset in between,
+/// e.g. because the router rebooted or the device was recreated
+pub fn throughput(previous: &CounterSample, current: &CounterSample) -> Option<Throughput> {
+    let elapsed = current.at.checked_duration_since(previous.at)?;
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 {
+        return None;
+    }
+
+    let (before, after) = (previous.counters, current.counters);
+    let rate = |before: u64, after: u64| Some(counter_delta(before, after, elapsed)? as f64 / secs);
+    Some(Throughput {
+        rx_bytes_per_sec: rate(before.rx_bytes, after.rx_bytes)?,
+        tx_bytes_per_sec: rate(before.tx_bytes, after.tx_bytes)?,
+        rx_packets_per_sec: rate(before.rx_packets, after.rx_packets)?,
+        tx_packets_per_sec: rate(before.tx_packets, after.tx_packets)?,
+    })
+}
+
+/// Increase of a counter over `elapsed`. A decrease only counts as a 32 bit counter
+/// wrapping around when both values fit in 32 bits and the increase that implies could
+/// have happened in `elapsed`. `None` when the counter went back because it was reset,
+/// e.g. a 64 bit counter after a reboot.
+pub fn counter_delta(before: u64, after: u64, elapsed: Duration) -> Option<u64> {
+    if after >= before {
+        return Some(after - before);
+    }
+    if before > u64::from(u32::MAX) {
+        return None;
+    }
+
+    let wrapped = after + (u64::from(u32::MAX) + 1 - before);
+    (wrapped as f64 <= MAX_WRAP_BYTES_PER_SEC * elapsed.as_secs_f64()).then_some(wrapped)
+}
+
+/// Format a byte rate in bits per second, like `12.3 Mbit/s`
+pub fn format_rate(bytes_per_sec: f64) -> String {
+    let bits = bytes_per_sec * 8.0;
+    if bits >= 1e9 {
+        format!("{:.1} Gbit/s", bits / 1e9)
+    } else if bits >= 1e6 {
+        format!("{:.1} Mbit/s", bits / 1e6)
+    } else if bits >= 1e3 {
+        format!("{:.1} kbit/s", bits / 1e3)
+    } else {
+        format!("{:.0} bit/s", bits)
+    }
+}
+
+/// Format a byte count with decimal units, like `12.3 GB`
+pub fn format_bytes(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1e12 {
+        format!("{:.1} TB", bytes / 1e12)
+    } else if bytes >= 1e9 {
+        format!("{:.1} GB", bytes / 1e9)
+    } else if bytes >= 1e6 {
+        format!("{:.1} MB", bytes / 1e6)
+    } else if bytes >= 1e3 {
+        format!("{:.1} kB", bytes / 1e3)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn increase() {
+        assert_eq!(counter_delta(1_000, 5_000, SECOND), Some(4_000));
+        assert_eq!(counter_delta(7, 7, SECOND), Some(0));
+    }
+
+    #[test]
+    fn wrap_of_32_bit_counter() {
+        let before = u64::from(u32::MAX) - 999;
+        assert_eq!(counter_delta(before, 500, SECOND), Some(1_500));
+    }
+
+    #[test]
+    fn reset_after_reboot() {
+        // 3 GB fits in 32 bits, but wrapping would mean 1.29 GB in two seconds
+        let elapsed = Duration::from_secs(2);
+        assert_eq!(counter_delta(3_000_000_000, 1_000_000, elapsed), None);
+        // Counters past 32 bits can't wrap at 32 bits
+        assert_eq!(counter_delta(50_000_000_000, 1_000_000, elapsed), None);
+    }
+
+    #[test]
+    fn wrap_needs_time() {
+        let before = u64::from(u32::MAX) - 200_000_000;
+        assert_eq!(counter_delta(before, 0, SECOND), None);
+        assert_eq!(
+            counter_delta(before, 0, Duration::from_secs(2)),
+            Some(200_000_001)
+        );
+        assert_eq!(counter_delta(before, 0, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn throughput_is_none_after_reset() {
+        let start = Instant::now();
+        let sample = |rx_bytes: u64, secs: u64| CounterSample {
+            counters: DeviceCounters {
+                rx_bytes,
+                ..DeviceCounters::default()
+            },
+            at: start + Duration::from_secs(secs),
+        };
+
+        let rates = throughput(&sample(1_000, 0), &sample(3_000, 2)).unwrap();
+        assert_eq!(rates.rx_bytes_per_sec, 1_000.0);
+        assert!(throughput(&sample(3_000_000_000, 2), &sample(1_000_000, 4)).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+use futures_util::Stream;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+
+use super::http::HttpTransport;
+use super::native::NativeSshTransport;
+use super::ssh::SshCommandTransport;
+use super::status::{AppError, OpenWrtConfig, Transport};
+
+/// A way of issuing ubus calls on the router
+pub trait RouterTransport {
+    /// Call `method` on the ubus `object` with `args` and return the decoded reply,
+    /// `Value::Null` for methods that don't reply
+    fn ubus_call(
+        &self,
+        object: &str,
+        method: &str,
+        args: Value,
+    ) -> impl Future<Output = Result<Value, AppError>> + Send;
+
+    /// Subscribe to events sent on ubus under `path`, like `ubus listen`. Resolves once
+    /// the router runs the listener, and the stream ends when the connection breaks.
+    fn listen(&self, path: &str) -> impl Future<Output = Result<EventStream, AppError>> + Send {
+        let path = path.to_string();
+        async move {
+            Err(AppError::Other(format!(
+                "Listening for {} events is not supported",
+                path
+            )))
+        }
+    }
+}
+
+/// ubus events as `{ "<type>": <data> }` objects, in the order the router sent them
+pub type EventStream = Pin<Box<dyn Stream<Item = Value> + Send>>;
+
+/// The transport selected in the router config
+#[derive(Debug, Clone)]
+pub enum AnyTransport {
+    SshCommand(SshCommandTransport),
+    NativeSsh(NativeSshTransport),
+    Http(HttpTransport),
+}
+
+impl AnyTransport {
+    pub fn new(config: &OpenWrtConfig) -> Self {
+        match config.transport {
+            Transport::SshCommand => Self::SshCommand(SshCommandTransport::new(config.clone())),
+            Transport::NativeSsh => Self::NativeSsh(NativeSshTransport::new(config.clone())),
+            Transport::HttpUbus => Self::Http(HttpTransport::new(config.clone())),
+        }
+    }
+
+    /// Close connections kept open for routers that are no longer among `configs`
+    pub fn retain_sessions(configs: &[&OpenWrtConfig]) {
+        super::native::retain_sessions(configs.iter().copied());
+    }
+}
+
+impl RouterTransport for AnyTransport {
+    async fn ubus_call(&self, object: &str, method: &str, args: Value) -> Result<Value, AppError> {
+        match self {
+            Self::SshCommand(transport) => transport.ubus_call(object, method, args).await,
+            Self::NativeSsh(transport) => transport.ubus_call(object, method, args).await,
+            Self::Http(transport) => transport.ubus_call(object, method, args).await,
+        }
+    }
+
+    async fn listen(&self, path: &str) -> Result<EventStream, AppError> {
+        match self {
+            Self::SshCommand(transport) => transport.listen(path).await,
+            Self::NativeSsh(transport) => transport.listen(path).await,
+            Self::Http(transport) => transport.listen(path).await,
+        }
+    }
+}
+
+/// Shell command line running `ubus call`, with the arguments quoted for the remote shell
+pub(super) fn ubus_command(object: &str, method: &str, args: &Value) -> String {
+    format!(
+        "ubus call {} {} {}",
+        shell_quote(object),
+        shell_quote(method),
+        shell_quote(&args.to_string())
+    )
+}
+
+/// Shell command line running `ubus listen` for events under `path`. An empty line comes
+/// first once the router found ubus, as events may not arrive for a long time.
+pub(super) fn ubus_listen_command(path: &str) -> String {
+    format!(
+        "command -v ubus >/dev/null && echo && exec ubus listen {}",
+        shell_quote(path)
+    )
+}
+
+/// Error for a listener whose remote command exited before it started
+pub(super) fn listen_error() -> AppError {
+    AppError::UbusUnavailable(String::from("ubus is not installed on the router"))
+}
+
+/// Exit code of a remote shell that could not find the command
+const COMMAND_NOT_FOUND: u32 = 127;
+
+/// Error for a remote `ubus call` that exited with `code`, which is the ubus status
+/// when ubus itself ran
+pub(super) fn command_error(
+    object: &str,
+    method: &str,
+    code: Option<u32>,
+    stderr: &str,
+) -> AppError {
+    match code {
+        Some(COMMAND_NOT_FOUND) => {
+            AppError::UbusUnavailable(String::from("ubus is not installed on the router"))
+        }
+        Some(code @ 1..=10) => ubus_status_error(object, method, code.into()),
+        _ => AppError::Other(format!("Command failed: {}", stderr.trim())),
+    }
+}
+
+/// Error for a ubus status other than `UBUS_STATUS_OK`, see `libubus.h`
+pub(super) fn ubus_status_error(object: &str, method: &str, status: i64) -> AppError {
+    let call = format!("{} {}", object, method);
+    match status {
+        // Per-interface objects only exist for interfaces netifd knows
+        3 | 4 => match object.strip_prefix("network.interface.") {
+            Some(interface) => AppError::InterfaceNotFound(interface.to_string()),
+            None => AppError::UbusUnavailable(format!("{} not found", call)),
+        },
+        6 => AppError::PermissionDenied(call),
+        7 => AppError::Timeout(call),
+        10 => AppError::UbusUnavailable(String::from("ubusd is not running")),
+        _ => AppError::Other(format!("ubus call {} failed with status {}", call, status)),
+    }
+}
+
+/// Wrap `value` in single quotes so the remote shell passes it through verbatim
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_map_to_variants() {
+        let wan = "network.interface.wan";
+        let stderr = "Command failed: Not found\n";
+        let failed = || AppError::Other(String::from("Command failed: Command failed: Not found"));
+        let status = |code| {
+            AppError::Other(format!(
+                "ubus call {} status failed with status {}",
+                wan, code
+            ))
+        };
+        let cases = [
+            (
+                wan,
+                Some(127),
+                AppError::UbusUnavailable(String::from("ubus is not installed on the router")),
+            ),
+            (wan, Some(1), status(1)),
+            (wan, Some(2), status(2)),
+            (
+                wan,
+                Some(3),
+                AppError::InterfaceNotFound(String::from("wan")),
+            ),
+            (
+                wan,
+                Some(4),
+                AppError::InterfaceNotFound(String::from("wan")),
+            ),
+            (
+                "network.device",
+                Some(4),
+                AppError::UbusUnavailable(String::from("network.device status not found")),
+            ),
+            (wan, Some(5), status(5)),
+            (
+                wan,
+                Some(6),
+                AppError::PermissionDenied(format!("{} status", wan)),
+            ),
+            (wan, Some(7), AppError::Timeout(format!("{} status", wan))),
+            (wan, Some(8), status(8)),
+            (wan, Some(9), status(9)),
+            (
+                wan,
+                Some(10),
+                AppError::UbusUnavailable(String::from("ubusd is not running")),
+            ),
+            (wan, Some(11), failed()),
+            // `ssh` itself failing, or a channel closed without an exit status
+            (wan, Some(255), failed()),
+            (wan, None, failed()),
+        ];
+
+        for (object, code, expected) in cases {
+            assert_eq!(
+                command_error(object, "status", code, stderr),
+                expected,
+                "{} exiting with {:?}",
+                object,
+                code
+            );
+        }
+    }
+}
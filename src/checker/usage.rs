@@ -0,0 +1,299 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::status::xdg_dir;
+use super::traffic::{counter_delta, DeviceCounters};
+
+/// Data transferred over an interface during the current billing period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InterfaceUsage {
+    /// First day of the billing period the totals belong to
+    pub period_start: NaiveDate,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Highest alert threshold already notified about in this period, in percent
+    #[serde(default)]
+    pub alerted_percent: u8,
+    /// Device and counters the totals were last advanced from
+    device: Option<String>,
+    last: Option<DeviceCounters>,
+    /// When `last` was read and the interface's uptime at the time
+    #[serde(default)]
+    last_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    uptime: Option<u64>,
+}
+
+impl InterfaceUsage {
+    fn new(period_start: NaiveDate) -> Self {
+        Self {
+            period_start,
+            rx_bytes: 0,
+            tx_bytes: 0,
+            alerted_percent: 0,
+            device: None,
+            last: None,
+            last_at: None,
+            uptime: None,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.rx_bytes.saturating_add(self.tx_bytes)
+    }
+
+    /// Share of `quota` used up, in percent
+    pub fn percent_of(&self, quota: u64) -> u64 {
+        (self.total() as u128 * 100 / u128::from(quota.max(1))) as u64
+    }
+}
+
+/// Counters of the device behind an interface, read at one point in time
+#[derive(Debug, Clone, Copy)]
+pub struct Reading<'a> {
+    pub device: &'a str,
+    pub counters: DeviceCounters,
+    /// Uptime of the interface when the counters were read
+    pub uptime: u64,
+    pub at: DateTime<Local>,
+}
+
+/// Usage totals by router name and interface name, kept in a JSON file so they survive
+/// applet restarts
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UsageStore {
+    routers: BTreeMap<String, BTreeMap<String, InterfaceUsage>>,
+}
+
+impl UsageStore {
+    /// Read the totals from `path`, starting over when the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|why| {
+                eprintln!("Error parsing {}: {}", path.display(), why);
+                Self::default()
+            }),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(why) => {
+                eprintln!("Error reading {}: {}", path.display(), why);
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the totals to `path`, replacing the previous file in one step
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_vec(self)?)?;
+        std::fs::rename(temp, path)
+    }
+
+    pub fn get(&self, router: &str, interface: &str) -> Option<&InterfaceUsage> {
+        self.routers.get(router)?.get(interface)
+    }
+
+    /// Add the traffic since the last reading to the totals of `interface`, starting from
+    /// zero when `period_start` moved on
+    pub fn record(
+        &mut self,
+        router: &str,
+        interface: &str,
+        reading: Reading,
+        period_start: NaiveDate,
+    ) -> &mut InterfaceUsage {
+        let Reading {
+            device,
+            counters,
+            uptime,
+            at: now,
+        } = reading;
+        let usage = self
+            .routers
+            .entry(router.to_string())
+            .or_default()
+            .entry(interface.to_string())
+            .or_insert_with(|| InterfaceUsage::new(period_start));
+
+        if usage.period_start != period_start {
+            usage.period_start = period_start;
+            usage.rx_bytes = 0;
+            usage.tx_bytes = 0;
+            usage.alerted_percent = 0;
+        }
+
+        match (usage.device.as_deref(), usage.last) {
+            // A first reading only sets the baseline, earlier traffic is unknown
+            (None, _) | (_, None) => {}
+            (Some(last_device), Some(last)) => {
+                // Counters of a recreated device or a rebooted router start at zero, so
+                // everything they show was transferred since the last reading
+                let same_device = last_device == device;
+                // Counters that went back while the interface restarted were reset rather
+                // than wrapped
+                let restarted = usage.uptime.is_some_and(|before| uptime < before);
+                let elapsed = match usage.last_at {
+                    Some(at) if !restarted => (now - at).to_std().unwrap_or_default(),
+                    _ => Duration::ZERO,
+                };
+                let delta = |before: u64, after: u64| match counter_delta(before, after, elapsed) {
+                    Some(delta) if same_device => delta,
+                    _ => after,
+                };
+                usage.rx_bytes = usage
+                    .rx_bytes
+                    .saturating_add(delta(last.rx_bytes, counters.rx_bytes));
+                usage.tx_bytes = usage
+                    .tx_bytes
+                    .saturating_add(delta(last.tx_bytes, counters.tx_bytes));
+            }
+        }
+        usage.device = Some(device.to_string());
+        usage.last = Some(counters);
+        usage.last_at = Some(now);
+        usage.uptime = Some(uptime);
+        usage
+    }
+}
+
+/// `$XDG_DATA_HOME/<app_id>/usage.json`, falling back to `~/.local/share`
+pub fn usage_path(app_id: &str) -> Option<PathBuf> {
+    Some(
+        xdg_dir("XDG_DATA_HOME", ".local/share")?
+            .join(app_id)
+            .join("usage.json"),
+    )
+}
+
+/// First day of the billing period `today` falls into when allowances renew on
+/// `billing_day` of every month
+pub fn period_start(today: NaiveDate, billing_day: u8) -> NaiveDate {
+    let day = u32::from(billing_day.clamp(1, 28));
+    let this_month = today.with_day(day).unwrap_or(today);
+    if today.day() >= day {
+        this_month
+    } else {
+        this_month
+            .checked_sub_months(chrono::Months::new(1))
+            .unwrap_or(this_month)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, day).unwrap()
+    }
+
+    fn counters(rx_bytes: u64, tx_bytes: u64) -> DeviceCounters {
+        DeviceCounters {
+            rx_bytes,
+            tx_bytes,
+            ..DeviceCounters::default()
+        }
+    }
+
+    /// Readings of one interface every two seconds from a fixed start
+    struct Readings {
+        store: UsageStore,
+        now: DateTime<Local>,
+    }
+
+    impl Readings {
+        fn new() -> Self {
+            Self {
+                store: UsageStore::default(),
+                now: Local::now(),
+            }
+        }
+
+        fn read(&mut self, device: &str, rx_bytes: u64, uptime: u64) -> (u64, u64) {
+            self.now += TimeDelta::seconds(2);
+            let reading = Reading {
+                device,
+                counters: counters(rx_bytes, 100),
+                uptime,
+                at: self.now,
+            };
+            let usage = self.store.record("Router", "wan", reading, date(1));
+            (usage.rx_bytes, usage.tx_bytes)
+        }
+    }
+
+    #[test]
+    fn first_reading_is_baseline() {
+        let mut readings = Readings::new();
+        assert_eq!(readings.read("eth1", 5_000_000, 10), (0, 0));
+        assert_eq!(readings.read("eth1", 5_001_000, 12), (1_000, 0));
+    }
+
+    #[test]
+    fn reboot_counts_only_new_traffic() {
+        let mut readings = Readings::new();
+        readings.read("eth1", 3_000_000_000, 500);
+        readings.read("eth1", 3_000_000_500, 502);
+        // The router rebooted, the 64 bit counter started over
+        assert_eq!(readings.read("eth1", 1_000_000, 5), (1_000_500, 0));
+    }
+
+    #[test]
+    fn restart_is_never_a_wrap() {
+        let mut readings = Readings::new();
+        let before = u64::from(u32::MAX) - 999;
+        readings.read("pppoe-wan", before, 500);
+        // Would be a plausible wrap, but the interface came up again in between
+        assert_eq!(readings.read("pppoe-wan", 500, 1), (500, 0));
+    }
+
+    #[test]
+    fn wrap_while_up_is_counted() {
+        let mut readings = Readings::new();
+        let before = u64::from(u32::MAX) - 999;
+        readings.read("eth1", before, 500);
+        assert_eq!(readings.read("eth1", 500, 502), (1_500, 0));
+    }
+
+    #[test]
+    fn new_device_counts_from_zero() {
+        let mut readings = Readings::new();
+        readings.read("wwan0", 8_000, 500);
+        assert_eq!(readings.read("wwan1", 9_000, 502), (9_000, 100));
+    }
+
+    #[test]
+    fn new_period_starts_over() {
+        let mut store = UsageStore::default();
+        let reading = |rx_bytes| Reading {
+            device: "eth1",
+            counters: counters(rx_bytes, 0),
+            uptime: 10,
+            at: Local::now(),
+        };
+        store.record("Router", "wan", reading(0), date(1));
+        store.record("Router", "wan", reading(700), date(1));
+        let usage = store.record("Router", "wan", reading(900), date(2));
+        assert_eq!((usage.period_start, usage.rx_bytes), (date(2), 200));
+    }
+
+    #[test]
+    fn period_starts_on_billing_day() {
+        assert_eq!(period_start(date(20), 15), date(15));
+        assert_eq!(period_start(date(15), 15), date(15));
+        assert_eq!(
+            period_start(date(3), 15),
+            NaiveDate::from_ymd_opt(2024, 4, 15).unwrap()
+        );
+        // Days past the 28th are clamped so every month has one
+        assert_eq!(period_start(date(30), 31), date(28));
+    }
+}
@@ -1,9 +1,129 @@
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::checker::status::OpenWrtConfig;
+use crate::notifications::ChangeKind;
+use crate::panel::DEFAULT_TEMPLATE;
 use cosmic::cosmic_config::{self, cosmic_config_derive::CosmicConfigEntry, CosmicConfigEntry};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Default, Clone, CosmicConfigEntry, Eq, PartialEq)]
+#[derive(Debug, Clone, CosmicConfigEntry, Eq, PartialEq)]
 #[version = 1]
 pub struct Config {
-    demo: String,
+    /// Routers whose interfaces are monitored, in the order they are shown.
+    pub routers: Vec<RouterProfile>,
+    /// Whether the panel shows the current download and upload speed next to the uptime.
+    pub show_rates_in_panel: bool,
+    /// Which interface changes raise a desktop notification.
+    pub notifications: NotificationSettings,
+    /// Text of the panel button, with placeholders such as `{uptime}` or `{ipv4}`.
+    pub panel_template: String,
+    /// Whether the panel shows only a state icon, e.g. in vertical panels.
+    pub panel_icon_only: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            routers: vec![RouterProfile::default()],
+            show_rates_in_panel: false,
+            notifications: NotificationSettings::default(),
+            panel_template: String::from(DEFAULT_TEMPLATE),
+            panel_icon_only: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Option<&RouterProfile> {
+        self.routers.iter().find(|profile| profile.name == name)
+    }
+}
+
+/// A named router and how to reach it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RouterProfile {
+    /// Unique name shown above the router's interfaces.
+    pub name: String,
+    pub router: OpenWrtConfig,
+    /// Kept apart from `router` so editing it doesn't reconnect.
+    #[serde(default)]
+    pub plan: DataPlan,
+}
+
+impl Default for RouterProfile {
+    fn default() -> Self {
+        Self {
+            name: String::from("Router"),
+            router: OpenWrtConfig::default(),
+            plan: DataPlan::default(),
+        }
+    }
+}
+
+/// Data allowances of a router's interfaces.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DataPlan {
+    /// Day of the month the allowances renew on, from 1 to 28.
+    pub billing_day: u8,
+    /// Monthly allowance in bytes by interface name.
+    pub quotas: BTreeMap<String, u64>,
+    /// Shares of a quota in percent that raise a notification once used up.
+    pub quota_alerts: Vec<u8>,
+}
+
+impl Default for DataPlan {
+    fn default() -> Self {
+        Self {
+            billing_day: 1,
+            quotas: BTreeMap::new(),
+            quota_alerts: vec![80, 100],
+        }
+    }
+}
+
+/// Which interface changes raise a desktop notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub went_down: bool,
+    pub came_up: bool,
+    pub address_changed: bool,
+    pub reconnected: bool,
+    pub proto_changed: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            went_down: true,
+            came_up: true,
+            address_changed: true,
+            reconnected: true,
+            proto_changed: true,
+        }
+    }
+}
+
+impl NotificationSettings {
+    pub fn enabled(&self, kind: ChangeKind) -> bool {
+        match kind {
+            ChangeKind::WentDown => self.went_down,
+            ChangeKind::CameUp => self.came_up,
+            ChangeKind::AddressChanged => self.address_changed,
+            ChangeKind::Reconnected => self.reconnected,
+            ChangeKind::ProtoChanged => self.proto_changed,
+        }
+    }
+
+    pub fn set(&mut self, kind: ChangeKind, enabled: bool) {
+        match kind {
+            ChangeKind::WentDown => self.went_down = enabled,
+            ChangeKind::CameUp => self.came_up = enabled,
+            ChangeKind::AddressChanged => self.address_changed = enabled,
+            ChangeKind::Reconnected => self.reconnected = enabled,
+            ChangeKind::ProtoChanged => self.proto_changed = enabled,
+        }
+    }
 }
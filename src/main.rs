@@ -1,9 +1,13 @@
 // SPDX-License-Identifier: MPL-2.0
 
 mod app;
+mod checker;
 mod config;
 mod i18n;
-mod checker;
+mod notifications;
+mod panel;
+mod secrets;
+mod settings;
 
 fn main() -> cosmic::iced::Result {
     // Get the system's preferred languages.
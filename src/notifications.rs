@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Desktop notifications through the `org.freedesktop.Notifications` D-Bus service.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+use zbus::zvariant::Value;
+
+use crate::checker::journal::JournalEvent;
+use crate::checker::status::{format_duration, InterfaceStatus};
+
+/// Name the notification server shows as the sender.
+const APP_NAME: &str = "Openwrt Interface Status";
+
+/// Icon shown next to the notifications, from the installed icon theme.
+const APP_ICON: &str = "com.github.mushonnip.OpenwrtInterfaceStatus";
+
+/// Most notifications shown about one interface within [`BURST_WINDOW`].
+const BURST_LIMIT: usize = 3;
+const BURST_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Shows a notification and returns the id the server assigned to it.
+pub async fn notify(summary: String, body: String) -> zbus::Result<u32> {
+    let connection = zbus::Connection::session().await?;
+    let hints: HashMap<&str, Value<'_>> = HashMap::new();
+    let reply = connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                APP_NAME,
+                0u32,
+                APP_ICON,
+                summary.as_str(),
+                body.as_str(),
+                Vec::<&str>::new(),
+                hints,
+                // Let the server pick how long the notification stays
+                -1i32,
+            ),
+        )
+        .await?;
+    reply.body().deserialize()
+}
+
+/// Kinds of interface changes that can be notified about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    WentDown,
+    CameUp,
+    AddressChanged,
+    /// The uptime started over while the interface stayed up, e.g. a PPP session or DHCP
+    /// lease that was renewed behind the scenes
+    Reconnected,
+    ProtoChanged,
+}
+
+impl ChangeKind {
+    pub const ALL: [ChangeKind; 5] = [
+        Self::WentDown,
+        Self::CameUp,
+        Self::AddressChanged,
+        Self::Reconnected,
+        Self::ProtoChanged,
+    ];
+
+    /// Label of the toggle that enables notifications of this kind.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::WentDown => "Interface went down",
+            Self::CameUp => "Interface came up",
+            Self::AddressChanged => "Address changed",
+            Self::Reconnected => "Silent reconnect",
+            Self::ProtoChanged => "Protocol changed",
+        }
+    }
+
+    /// How a change of this kind is recorded in the event journal.
+    pub fn journal_event(self) -> JournalEvent {
+        match self {
+            Self::WentDown => JournalEvent::WentDown,
+            Self::CameUp => JournalEvent::CameUp,
+            Self::AddressChanged => JournalEvent::AddressChanged,
+            Self::Reconnected => JournalEvent::Reconnected,
+            Self::ProtoChanged => JournalEvent::ProtoChanged,
+        }
+    }
+
+    fn summary(self, interface: &str) -> String {
+        match self {
+            Self::WentDown => format!("{} went down", interface),
+            Self::CameUp => format!("{} is up again", interface),
+            Self::AddressChanged => format!("{} got a new address", interface),
+            Self::Reconnected => format!("{} reconnected", interface),
+            Self::ProtoChanged => format!("{} changed its protocol", interface),
+        }
+    }
+}
+
+/// One difference between two statuses of the same interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// What exactly changed, e.g. the old and new address.
+    pub detail: String,
+}
+
+/// Differences between the `previous` and `current` status of an interface worth telling
+/// the user about.
+pub fn status_changes(previous: &InterfaceStatus, current: &InterfaceStatus) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut push = |kind, detail: String| changes.push(Change { kind, detail });
+
+    match (previous.up, current.up) {
+        (true, false) => push(
+            ChangeKind::WentDown,
+            format!("Was up for {}", previous.format_uptime()),
+        ),
+        (false, true) => push(
+            ChangeKind::CameUp,
+            match current.primary_ipv4() {
+                Some(address) => format!("IPv4 {}", address),
+                None => String::from("Connected"),
+            },
+        ),
+        (true, true) => {
+            if current.uptime < previous.uptime {
+                push(
+                    ChangeKind::Reconnected,
+                    format!(
+                        "Uptime started over after {}",
+                        format_duration(previous.uptime)
+                    ),
+                );
+            }
+
+            if previous.primary_ipv4() != current.primary_ipv4() {
+                let show = |address: Option<_>| {
+                    address.map_or_else(|| String::from("none"), |address| address.to_string())
+                };
+                push(
+                    ChangeKind::AddressChanged,
+                    format!(
+                        "IPv4 {} → {}",
+                        show(previous.primary_ipv4()),
+                        show(current.primary_ipv4())
+                    ),
+                );
+            }
+
+            let global = |status: &InterfaceStatus| -> Vec<Ipv6Addr> {
+                let mut addresses: Vec<_> = status
+                    .global_ipv6_addresses()
+                    .map(|address| address.address)
+                    .collect();
+                addresses.sort_unstable();
+                addresses
+            };
+            let (before, after) = (global(previous), global(current));
+            if before != after {
+                push(
+                    ChangeKind::AddressChanged,
+                    match after.first() {
+                        Some(address) => format!("IPv6 {}", address),
+                        None => String::from("No global IPv6 address left"),
+                    },
+                );
+            }
+        }
+        (false, false) => {}
+    }
+
+    if previous.proto != current.proto {
+        let show = |proto: &Option<String>| proto.clone().unwrap_or_else(|| String::from("none"));
+        push(
+            ChangeKind::ProtoChanged,
+            format!("{} → {}", show(&previous.proto), show(&current.proto)),
+        );
+    }
+
+    changes
+}
+
+/// Summary and body of one notification about the `changes` of `interface`.
+pub fn describe(
+    router: &str,
+    interface: &str,
+    changes: &[Change],
+    suppressed: u32,
+) -> (String, String) {
+    let summary = match changes.first() {
+        Some(change) => format!("{}: {}", router, change.kind.summary(interface)),
+        None => String::from(router),
+    };
+    let mut lines: Vec<String> = changes.iter().map(|change| change.detail.clone()).collect();
+    if suppressed > 0 {
+        lines.push(format!("{} earlier changes were not notified", suppressed));
+    }
+    (summary, lines.join("\n"))
+}
+
+/// Caps the notifications about each interface so a flapping link doesn't flood the
+/// desktop.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// When notifications were shown, by router and interface name.
+    shown: BTreeMap<(String, String), VecDeque<Instant>>,
+    /// Notifications held back since the last one shown, by router and interface name.
+    suppressed: BTreeMap<(String, String), u32>,
+}
+
+impl RateLimiter {
+    /// Whether a notification about `interface` of `router` may be shown at `now`.
+    ///
+    /// Returns how many notifications were held back since the last one shown.
+    pub fn allow(&mut self, router: &str, interface: &str, now: Instant) -> Option<u32> {
+        let key = (router.to_string(), interface.to_string());
+        let shown = self.shown.entry(key.clone()).or_default();
+        while shown
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) > BURST_WINDOW)
+        {
+            shown.pop_front();
+        }
+
+        if shown.len() >= BURST_LIMIT {
+            *self.suppressed.entry(key).or_default() += 1;
+            return None;
+        }
+        shown.push_back(now);
+        Some(self.suppressed.remove(&key).unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn status(up: bool, uptime: u64, ipv4: &str, ipv6: &str, proto: &str) -> InterfaceStatus {
+        serde_json::from_value(json!({
+            "up": up,
+            "uptime": uptime,
+            "proto": proto,
+            "ipv4-address": [{ "address": ipv4, "mask": 24 }],
+            "ipv6-address": [{ "address": ipv6, "mask": 64 }],
+        }))
+        .unwrap()
+    }
+
+    fn kinds(changes: &[Change]) -> Vec<ChangeKind> {
+        changes.iter().map(|change| change.kind).collect()
+    }
+
+    const IPV4: &str = "84.115.210.45";
+    const IPV6: &str = "2a02:8108:1140:42::1a2b";
+
+    #[test]
+    fn nothing_changed() {
+        let wan = status(true, 3600, IPV4, IPV6, "dhcp");
+        let later = status(true, 3660, IPV4, IPV6, "dhcp");
+        assert!(status_changes(&wan, &later).is_empty());
+    }
+
+    #[test]
+    fn went_down() {
+        let up = status(true, 3600, IPV4, IPV6, "dhcp");
+        let down = status(false, 0, IPV4, IPV6, "dhcp");
+
+        let changes = status_changes(&up, &down);
+        assert_eq!(kinds(&changes), [ChangeKind::WentDown]);
+        assert!(changes[0].detail.starts_with("Was up for"));
+    }
+
+    #[test]
+    fn came_up() {
+        let down = status(false, 0, IPV4, IPV6, "dhcp");
+        let up = status(true, 5, IPV4, IPV6, "dhcp");
+
+        let changes = status_changes(&down, &up);
+        assert_eq!(kinds(&changes), [ChangeKind::CameUp]);
+        assert_eq!(changes[0].detail, format!("IPv4 {}", IPV4));
+    }
+
+    #[test]
+    fn uptime_started_over() {
+        let before = status(true, 3600, IPV4, IPV6, "dhcp");
+        let after = status(true, 30, IPV4, IPV6, "dhcp");
+
+        assert_eq!(
+            kinds(&status_changes(&before, &after)),
+            [ChangeKind::Reconnected]
+        );
+    }
+
+    #[test]
+    fn ipv4_address_changed() {
+        let before = status(true, 3600, IPV4, IPV6, "dhcp");
+        let after = status(true, 3660, "84.115.211.7", IPV6, "dhcp");
+
+        let changes = status_changes(&before, &after);
+        assert_eq!(kinds(&changes), [ChangeKind::AddressChanged]);
+        assert_eq!(changes[0].detail, format!("IPv4 {} → 84.115.211.7", IPV4));
+    }
+
+    #[test]
+    fn ipv6_address_changed() {
+        let before = status(true, 3600, IPV4, IPV6, "dhcp");
+        let after = status(true, 3660, IPV4, "2a02:8108:1140:42::77", "dhcp");
+
+        let changes = status_changes(&before, &after);
+        assert_eq!(kinds(&changes), [ChangeKind::AddressChanged]);
+        assert_eq!(changes[0].detail, "IPv6 2a02:8108:1140:42::77");
+    }
+
+    #[test]
+    fn proto_changed() {
+        let before = status(false, 0, IPV4, IPV6, "dhcp");
+        let after = status(false, 0, IPV4, IPV6, "pppoe");
+
+        let changes = status_changes(&before, &after);
+        assert_eq!(kinds(&changes), [ChangeKind::ProtoChanged]);
+        assert_eq!(changes[0].detail, "dhcp → pppoe");
+    }
+
+    #[test]
+    fn reconnect_with_new_address() {
+        let before = status(true, 3600, IPV4, IPV6, "dhcp");
+        let after = status(true, 10, "84.115.211.7", IPV6, "dhcp");
+
+        assert_eq!(
+            kinds(&status_changes(&before, &after)),
+            [ChangeKind::Reconnected, ChangeKind::AddressChanged]
+        );
+    }
+
+    #[test]
+    fn burst_is_limited_and_suppressed_count_reported() {
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        for secs in 0..BURST_LIMIT as u64 {
+            assert_eq!(limiter.allow("Router", "wan", at(secs)), Some(0));
+        }
+        assert_eq!(limiter.allow("Router", "wan", at(10)), None);
+        assert_eq!(limiter.allow("Router", "wan", at(20)), None);
+        // Other interfaces have bursts of their own
+        assert_eq!(limiter.allow("Router", "lte", at(20)), Some(0));
+
+        // Once the first notification left the window one more may be shown, telling
+        // how many were held back
+        let later = BURST_WINDOW.as_secs() + 1;
+        assert_eq!(limiter.allow("Router", "wan", at(later)), Some(2));
+        assert_eq!(limiter.allow("Router", "wan", at(later + 1)), Some(0));
+        assert_eq!(limiter.allow("Router", "wan", at(later + 1)), None);
+    }
+}
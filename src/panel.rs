@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Template of the text shown in the panel button.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Template the panel used before it could be configured.
+pub const DEFAULT_TEMPLATE: &str = "{uptime}";
+
+/// Values a template can refer to by name in braces, e.g. `{ipv4}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    Uptime,
+    Ipv4,
+    Ipv6,
+    Proto,
+    Device,
+    RxRate,
+    TxRate,
+    Interface,
+    Router,
+}
+
+impl Placeholder {
+    pub const ALL: [Placeholder; 9] = [
+        Self::Uptime,
+        Self::Ipv4,
+        Self::Ipv6,
+        Self::Proto,
+        Self::Device,
+        Self::RxRate,
+        Self::TxRate,
+        Self::Interface,
+        Self::Router,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Uptime => "uptime",
+            Self::Ipv4 => "ipv4",
+            Self::Ipv6 => "ipv6",
+            Self::Proto => "proto",
+            Self::Device => "device",
+            Self::RxRate => "rx_rate",
+            Self::TxRate => "tx_rate",
+            Self::Interface => "interface",
+            Self::Router => "router",
+        }
+    }
+}
+
+/// Literal text or a placeholder of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// A parsed panel template. `{{` and `}}` stand for literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelTemplate {
+    segments: Vec<Segment>,
+}
+
+/// Why a template could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    Empty,
+    /// A `{` without its closing `}`.
+    Unclosed,
+    /// A `}` that closes nothing.
+    Unopened,
+    UnknownPlaceholder(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Template must not be empty"),
+            Self::Unclosed => write!(f, "Missing '}}' after a placeholder"),
+            Self::Unopened => write!(f, "Unexpected '}}', write '}}}}' for a literal brace"),
+            Self::UnknownPlaceholder(name) => write!(f, "Unknown placeholder {{{}}}", name),
+        }
+    }
+}
+
+impl FromStr for PanelTemplate {
+    type Err = TemplateError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        if template.trim().is_empty() {
+            return Err(TemplateError::Empty);
+        }
+
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+                '}' => return Err(TemplateError::Unopened),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::Unclosed),
+                        }
+                    }
+                    let placeholder = Placeholder::ALL
+                        .into_iter()
+                        .find(|placeholder| placeholder.name() == name.trim())
+                        .ok_or(TemplateError::UnknownPlaceholder(name))?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Default for PanelTemplate {
+    fn default() -> Self {
+        Self {
+            segments: vec![Segment::Placeholder(Placeholder::Uptime)],
+        }
+    }
+}
+
+impl PanelTemplate {
+    /// Fills in the placeholders, showing `–` for values that are not known.
+    pub fn render(&self, value: impl Fn(Placeholder) -> Option<String>) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Placeholder(placeholder) => {
+                    value(*placeholder).unwrap_or_else(|| String::from("–"))
+                }
+            })
+            .collect()
+    }
+
+    pub fn uses(&self, placeholder: Placeholder) -> bool {
+        self.segments.contains(&Segment::Placeholder(placeholder))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> String {
+        let template: PanelTemplate = template.parse().unwrap();
+        template.render(|placeholder| match placeholder {
+            Placeholder::Uptime => Some(String::from("3h 2m")),
+            Placeholder::Ipv4 => Some(String::from("192.0.2.7")),
+            Placeholder::Interface => Some(String::from("wan")),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn placeholders_and_text() {
+        assert_eq!(render("{interface}: {uptime}"), "wan: 3h 2m");
+        assert_eq!(render("{ipv4}"), "192.0.2.7");
+        assert_eq!(render("up"), "up");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{{uptime}}}"), "{3h 2m}");
+        assert_eq!(render("{{uptime}}"), "{uptime}");
+    }
+
+    #[test]
+    fn whitespace_inside_braces_is_ignored() {
+        assert_eq!(render("{ uptime }"), "3h 2m");
+        assert_eq!(render(" {ipv4} "), " 192.0.2.7 ");
+    }
+
+    #[test]
+    fn missing_values_show_dash() {
+        assert_eq!(render("{rx_rate} / {tx_rate}"), "– / –");
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |template: &str| template.parse::<PanelTemplate>();
+        assert_eq!(parse(""), Err(TemplateError::Empty));
+        assert_eq!(parse("  "), Err(TemplateError::Empty));
+        assert_eq!(parse("{uptime"), Err(TemplateError::Unclosed));
+        assert_eq!(parse("uptime}"), Err(TemplateError::Unopened));
+        assert_eq!(
+            parse("{speed}"),
+            Err(TemplateError::UnknownPlaceholder(String::from("speed")))
+        );
+        assert_eq!(
+            parse("{}"),
+            Err(TemplateError::UnknownPlaceholder(String::new()))
+        );
+    }
+
+    #[test]
+    fn default_matches_default_template() {
+        assert_eq!(DEFAULT_TEMPLATE.parse(), Ok(PanelTemplate::default()));
+    }
+
+    #[test]
+    fn uses_placeholder() {
+        let template: PanelTemplate = "{{rx_rate}} {tx_rate}".parse().unwrap();
+        assert!(template.uses(Placeholder::TxRate));
+        assert!(!template.uses(Placeholder::RxRate));
+    }
+}
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Router passwords kept in the keyring through the `org.freedesktop.secrets` D-Bus service.
+
+use std::collections::{BTreeMap, HashMap};
+use zbus::zvariant::{OwnedObjectPath, OwnedValue, Value};
+
+const SERVICE: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+
+/// Collection new passwords are stored in, usually the login keyring.
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+/// Attribute telling the applet's items apart from those of other applications.
+const APPLICATION: &str = "com.github.mushonnip.OpenwrtInterfaceStatus";
+
+/// Path the service returns when no prompt is needed.
+const NO_PROMPT: &str = "/";
+
+/// A secret as the service transfers it: session, parameters, value and content type.
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// Opens a session that transfers secrets unencrypted, which is fine on the session bus.
+async fn open_session(connection: &zbus::Connection) -> zbus::Result<OwnedObjectPath> {
+    let reply = connection
+        .call_method(
+            Some(SERVICE),
+            SERVICE_PATH,
+            Some("org.freedesktop.Secret.Service"),
+            "OpenSession",
+            &("plain", Value::from("")),
+        )
+        .await?;
+    let (_output, session): (OwnedValue, OwnedObjectPath) = reply.body().deserialize()?;
+    Ok(session)
+}
+
+/// Unlocked and locked items holding the password of the router profile `name`.
+async fn find_items(
+    connection: &zbus::Connection,
+    name: &str,
+) -> zbus::Result<(Vec<OwnedObjectPath>, Vec<OwnedObjectPath>)> {
+    let attributes = HashMap::from([("application", APPLICATION), ("profile", name)]);
+    let reply = connection
+        .call_method(
+            Some(SERVICE),
+            SERVICE_PATH,
+            Some("org.freedesktop.Secret.Service"),
+            "SearchItems",
+            &attributes,
+        )
+        .await?;
+    reply.body().deserialize()
+}
+
+/// Reads the stored passwords of the router profiles `names`, leaving out those without one.
+pub async fn load_passwords(names: Vec<String>) -> zbus::Result<BTreeMap<String, String>> {
+    let connection = zbus::Connection::session().await?;
+    let session = open_session(&connection).await?;
+
+    let mut passwords = BTreeMap::new();
+    for name in names {
+        let (unlocked, locked) = find_items(&connection, &name).await?;
+        let Some(item) = unlocked.first() else {
+            if !locked.is_empty() {
+                eprintln!("The password of {} is in a locked keyring", name);
+            }
+            continue;
+        };
+
+        let reply = connection
+            .call_method(
+                Some(SERVICE),
+                item.as_str(),
+                Some("org.freedesktop.Secret.Item"),
+                "GetSecret",
+                &session,
+            )
+            .await?;
+        let (_session, _parameters, value, _content_type): Secret = reply.body().deserialize()?;
+        passwords.insert(name, String::from_utf8_lossy(&value).into_owned());
+    }
+    Ok(passwords)
+}
+
+/// Stores the password of the router profile `name`, or removes it when `password` is empty.
+pub async fn store_password(name: String, password: String) -> zbus::Result<()> {
+    let connection = zbus::Connection::session().await?;
+    if password.is_empty() {
+        let (unlocked, locked) = find_items(&connection, &name).await?;
+        for item in unlocked.iter().chain(&locked) {
+            connection
+                .call_method(
+                    Some(SERVICE),
+                    item.as_str(),
+                    Some("org.freedesktop.Secret.Item"),
+                    "Delete",
+                    &(),
+                )
+                .await?;
+        }
+        return Ok(());
+    }
+
+    let session = open_session(&connection).await?;
+    let label = format!("OpenWrt router {}", name);
+    let attributes = HashMap::from([("application", APPLICATION), ("profile", name.as_str())]);
+    let properties: HashMap<&str, Value<'_>> = HashMap::from([
+        (
+            "org.freedesktop.Secret.Item.Label",
+            Value::from(label.as_str()),
+        ),
+        (
+            "org.freedesktop.Secret.Item.Attributes",
+            Value::from(attributes),
+        ),
+    ]);
+    let secret: Secret = (
+        session,
+        Vec::new(),
+        password.into_bytes(),
+        String::from("text/plain"),
+    );
+
+    let reply = connection
+        .call_method(
+            Some(SERVICE),
+            DEFAULT_COLLECTION,
+            Some("org.freedesktop.Secret.Collection"),
+            "CreateItem",
+            // Replace the item of an earlier password with the same attributes
+            &(properties, secret, true),
+        )
+        .await?;
+    let (_item, prompt): (OwnedObjectPath, OwnedObjectPath) = reply.body().deserialize()?;
+    if prompt.as_str() != NO_PROMPT {
+        return Err(zbus::Error::Failure(String::from(
+            "The keyring is locked, the password was not stored",
+        )));
+    }
+    Ok(())
+}
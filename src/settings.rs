@@ -0,0 +1,475 @@
+// SPDX-License-Identifier: MPL-2.0
+
+//! Editable form state for the router connection settings shown in the popup.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv6Addr};
+
+use crate::checker::status::{expand_home, HttpScheme, OpenWrtConfig, Transport};
+use crate::config::{DataPlan, RouterProfile};
+
+/// Identifies one input of the settings form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsField {
+    Name,
+    Host,
+    Port,
+    Username,
+    Interfaces,
+    PrivateKeyPath,
+    Password,
+    BillingDay,
+    Quotas,
+    QuotaAlerts,
+}
+
+/// Text buffers backing the settings inputs.
+///
+/// Kept apart from the saved [`OpenWrtConfig`] so half-typed values never reach the checker.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsForm {
+    pub name: String,
+    pub host: String,
+    pub port: String,
+    pub username: String,
+    /// Comma separated interface names
+    pub interfaces: String,
+    pub private_key_path: String,
+    pub transport: Transport,
+    pub password: String,
+    pub scheme: HttpScheme,
+    pub billing_day: String,
+    /// Comma separated `interface=amount` pairs, e.g. `wwan=50GB`
+    pub quotas: String,
+    /// Comma separated percentages
+    pub quota_alerts: String,
+}
+
+/// Validation messages for each field of a [`SettingsForm`], `None` when the field is valid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsErrors {
+    pub name: Option<&'static str>,
+    pub host: Option<&'static str>,
+    pub port: Option<&'static str>,
+    pub username: Option<&'static str>,
+    pub interfaces: Option<&'static str>,
+    pub private_key_path: Option<&'static str>,
+    pub billing_day: Option<&'static str>,
+    pub quotas: Option<&'static str>,
+    pub quota_alerts: Option<&'static str>,
+}
+
+impl SettingsErrors {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl SettingsForm {
+    pub fn from_profile(profile: &RouterProfile) -> Self {
+        let config = &profile.router;
+        Self {
+            name: profile.name.clone(),
+            host: config.host.clone(),
+            port: config.port.to_string(),
+            username: config.username.clone(),
+            interfaces: config.interfaces.join(", "),
+            private_key_path: config.private_key_path.clone().unwrap_or_default(),
+            transport: config.transport,
+            password: config.password.clone(),
+            scheme: config.scheme,
+            billing_day: profile.plan.billing_day.to_string(),
+            quotas: profile
+                .plan
+                .quotas
+                .iter()
+                .map(|(interface, bytes)| format!("{}={}", interface, format_amount(*bytes)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            quota_alerts: profile
+                .plan
+                .quota_alerts
+                .iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    /// Port the selected transport and scheme usually listen on.
+    pub fn default_port(&self) -> u16 {
+        match self.transport {
+            Transport::HttpUbus => self.scheme.default_port(),
+            transport => transport.default_port(),
+        }
+    }
+
+    pub fn set(&mut self, field: SettingsField, value: String) {
+        match field {
+            SettingsField::Name => self.name = value,
+            SettingsField::Host => self.host = value,
+            SettingsField::Port => self.port = value,
+            SettingsField::Username => self.username = value,
+            SettingsField::Interfaces => self.interfaces = value,
+            SettingsField::PrivateKeyPath => self.private_key_path = value,
+            SettingsField::Password => self.password = value,
+            SettingsField::BillingDay => self.billing_day = value,
+            SettingsField::Quotas => self.quotas = value,
+            SettingsField::QuotaAlerts => self.quota_alerts = value,
+        }
+    }
+
+    /// Checks every field and builds the router profile when all of them are valid.
+    ///
+    /// `taken` holds the names of the other profiles, which this one must not reuse.
+    pub fn validate(&self, taken: &[&str]) -> Result<RouterProfile, SettingsErrors> {
+        let name = self.name.trim();
+        let host = self.host.trim();
+        let port = self
+            .port
+            .trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port != 0);
+        let username = self.username.trim();
+        let mut interfaces: Vec<String> = Vec::new();
+        for name in self.interfaces.split(',').map(str::trim) {
+            if !name.is_empty() && !interfaces.iter().any(|interface| interface == name) {
+                interfaces.push(name.to_string());
+            }
+        }
+        let private_key_path = self.private_key_path.trim();
+        let billing_day = self
+            .billing_day
+            .trim()
+            .parse::<u8>()
+            .ok()
+            .filter(|day| (1..=28).contains(day));
+        let quotas = parse_quotas(&self.quotas, &interfaces);
+        let quota_alerts = parse_quota_alerts(&self.quota_alerts);
+
+        let errors = SettingsErrors {
+            name: validate_name(name, taken).err(),
+            host: validate_host(host).err(),
+            port: port
+                .is_none()
+                .then_some("Port must be a number between 1 and 65535"),
+            username: validate_username(username).err(),
+            interfaces: validate_interfaces(&interfaces).err(),
+            private_key_path: if self.transport.uses_ssh() {
+                validate_private_key_path(private_key_path).err()
+            } else {
+                None
+            },
+            billing_day: billing_day
+                .is_none()
+                .then_some("Billing day must be a number between 1 and 28"),
+            quotas: quotas.as_ref().err().copied(),
+            quota_alerts: quota_alerts.as_ref().err().copied(),
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(RouterProfile {
+            name: name.to_string(),
+            router: OpenWrtConfig {
+                host: host.to_string(),
+                port: port.unwrap_or(22),
+                username: username.to_string(),
+                interfaces,
+                private_key_path: (!private_key_path.is_empty())
+                    .then(|| private_key_path.to_string()),
+                host_key: None,
+                transport: self.transport,
+                password: self.password.clone(),
+                scheme: self.scheme,
+            },
+            plan: DataPlan {
+                billing_day: billing_day.unwrap_or(1),
+                quotas: quotas.unwrap_or_default(),
+                quota_alerts: quota_alerts.unwrap_or_default(),
+            },
+        })
+    }
+}
+
+/// Parses `interface=amount` pairs for interfaces in `interfaces`, amounts in bytes
+/// with an optional decimal unit such as `50GB`
+fn parse_quotas(text: &str, interfaces: &[String]) -> Result<BTreeMap<String, u64>, &'static str> {
+    let mut quotas = BTreeMap::new();
+    for pair in text
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let (interface, amount) = pair
+            .split_once('=')
+            .ok_or("Quotas must look like 'wwan=50GB, wan=1TB'")?;
+        let interface = interface.trim();
+        if !interfaces.iter().any(|name| name == interface) {
+            return Err("Quotas can only be set for monitored interfaces");
+        }
+        let bytes = parse_amount(amount.trim()).ok_or("Quota amounts must look like '50GB'")?;
+        quotas.insert(interface.to_string(), bytes);
+    }
+    Ok(quotas)
+}
+
+fn parse_amount(amount: &str) -> Option<u64> {
+    let split = amount
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(amount.len());
+    let (number, unit) = amount.split_at(split);
+    let factor = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1e0,
+        "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        _ => return None,
+    };
+    let bytes = number.parse::<f64>().ok()? * factor;
+    (bytes >= 1.0 && bytes < u64::MAX as f64).then_some(bytes as u64)
+}
+
+/// Shortest exact spelling of a byte amount that [`parse_amount`] reads back
+fn format_amount(bytes: u64) -> String {
+    [
+        (1_000_000_000_000, "TB"),
+        (1_000_000_000, "GB"),
+        (1_000_000, "MB"),
+        (1_000, "KB"),
+    ]
+    .into_iter()
+    .find(|(factor, _)| bytes % factor == 0)
+    .map(|(factor, unit)| format!("{}{}", bytes / factor, unit))
+    .unwrap_or_else(|| bytes.to_string())
+}
+
+fn parse_quota_alerts(text: &str) -> Result<Vec<u8>, &'static str> {
+    let mut alerts = Vec::new();
+    for percent in text
+        .split(',')
+        .map(str::trim)
+        .filter(|percent| !percent.is_empty())
+    {
+        match percent.trim_end_matches('%').parse::<u8>() {
+            Ok(percent) if (1..=100).contains(&percent) => alerts.push(percent),
+            _ => return Err("Alerts must be percentages between 1 and 100"),
+        }
+    }
+    alerts.sort_unstable();
+    alerts.dedup();
+    Ok(alerts)
+}
+
+fn validate_name(name: &str, taken: &[&str]) -> Result<(), &'static str> {
+    if name.is_empty() {
+        Err("Name is required")
+    } else if taken.contains(&name) {
+        Err("Another router already has this name")
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_host(host: &str) -> Result<(), &'static str> {
+    if host.is_empty() {
+        return Err("Host is required");
+    }
+
+    let literal = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    // Link-local addresses name the interface they are reached through, e.g. `fe80::1%eth0`
+    let (address, zone) = match literal.split_once('%') {
+        Some((address, zone)) => (address, Some(zone)),
+        None => (literal, None),
+    };
+    let valid_zone = zone.is_none_or(|zone| {
+        !zone.is_empty()
+            && zone
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+    });
+    if address.parse::<Ipv6Addr>().is_ok() && valid_zone
+        || zone.is_none() && address.parse::<IpAddr>().is_ok()
+    {
+        return Ok(());
+    }
+
+    // RFC 1123 host name: dot separated labels of letters, digits and inner hyphens
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if host.len() <= 253 && host.split('.').all(valid_label) {
+        Ok(())
+    } else {
+        Err("Host must be an IP address or host name")
+    }
+}
+
+fn validate_username(username: &str) -> Result<(), &'static str> {
+    if username.is_empty() {
+        Err("Username is required")
+    } else if username.chars().any(|c| c.is_whitespace() || c == '@') {
+        Err("Username must not contain spaces or '@'")
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_interfaces(interfaces: &[String]) -> Result<(), &'static str> {
+    // Names end up in remote `ubus` command lines, so only allow what netifd accepts
+    let valid_name = |interface: &String| {
+        interface
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+    };
+
+    if interfaces.is_empty() {
+        Err("At least one interface is required")
+    } else if !interfaces.iter().all(valid_name) {
+        Err("Interface names may only contain letters, digits, '_', '-' and '.'")
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_private_key_path(path: &str) -> Result<(), &'static str> {
+    if path.is_empty() || expand_home(path).is_file() {
+        Ok(())
+    } else {
+        Err("Key file does not exist")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form() -> SettingsForm {
+        SettingsForm {
+            name: String::from("Home"),
+            host: String::from("192.168.1.1"),
+            port: String::from("22"),
+            username: String::from("root"),
+            interfaces: String::from("wan"),
+            billing_day: String::from("1"),
+            quota_alerts: String::from("80, 100"),
+            ..SettingsForm::default()
+        }
+    }
+
+    #[test]
+    fn hosts() {
+        for host in [
+            "192.168.1.1",
+            "openwrt.lan",
+            "router",
+            "my-router.example.com",
+            "fd00::1",
+            "[fd00::1]",
+            "fe80::1%eth0",
+            "[fe80::1%br-lan]",
+            "[fe80::1%wlp2s0.1]",
+        ] {
+            assert_eq!(validate_host(host), Ok(()), "{}", host);
+        }
+
+        for host in [
+            "",
+            "[fd00::1",
+            "fd00::1]",
+            "[192.168.1.1]x",
+            "192.168.1.1%eth0",
+            "fe80::1%",
+            "fe80::1%eth 0",
+            "-router",
+            "router-.lan",
+            "open_wrt.lan",
+            "openwrt..lan",
+            "root@openwrt.lan",
+            "openwrt.lan:22",
+        ] {
+            assert!(validate_host(host).is_err(), "{}", host);
+        }
+        assert!(validate_host(&format!("{}.lan", "a".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn ports() {
+        for (port, valid) in [
+            ("22", true),
+            (" 2222 ", true),
+            ("65535", true),
+            ("0", false),
+            ("65536", false),
+            ("-1", false),
+            ("ssh", false),
+            ("", false),
+        ] {
+            let form = SettingsForm {
+                port: port.to_string(),
+                ..form()
+            };
+            let errors = form.validate(&[]).err().unwrap_or_default();
+            assert_eq!(errors.port.is_none(), valid, "{:?}", port);
+        }
+    }
+
+    #[test]
+    fn interfaces() {
+        let validate = |interfaces: &str| {
+            SettingsForm {
+                interfaces: interfaces.to_string(),
+                ..form()
+            }
+            .validate(&[])
+        };
+
+        let profile = validate(" wan, wan6,,wan , lte_1, vpn-0, br.lan").unwrap();
+        assert_eq!(
+            profile.router.interfaces,
+            ["wan", "wan6", "lte_1", "vpn-0", "br.lan"]
+        );
+
+        for interfaces in ["", " , ", "wan; reboot", "wan $(reboot)", "w'an", "wan lan"] {
+            let errors = validate(interfaces).unwrap_err();
+            assert!(errors.interfaces.is_some(), "{:?}", interfaces);
+        }
+    }
+
+    #[test]
+    fn names_must_be_unique() {
+        assert!(form().validate(&["Office"]).is_ok());
+        let errors = form().validate(&["Office", "Home"]).unwrap_err();
+        assert!(errors.name.is_some());
+    }
+
+    #[test]
+    fn key_path_only_checked_for_ssh() {
+        let missing = SettingsForm {
+            private_key_path: String::from("/nonexistent/id_ed25519"),
+            ..form()
+        };
+        assert!(missing
+            .validate(&[])
+            .unwrap_err()
+            .private_key_path
+            .is_some());
+
+        let http = SettingsForm {
+            transport: Transport::HttpUbus,
+            ..missing
+        };
+        assert!(http.validate(&[]).is_ok());
+    }
+}